@@ -0,0 +1,216 @@
+// Map cells along each side of one air cell. The air only needs to be smooth, not sharp,
+// so a coarse grid keeps it cheap.
+pub const AIR_CELL: usize = 4;
+// Passes of the pressure solve that keeps the air from bunching up or thinning out.
+const ITERATIONS: usize = 20;
+// Fraction of its speed the air keeps each tick.
+const RETENTION: f32 = 0.98;
+// Air slower than this, in cells per tick, has settled and is stopped outright, so
+// still air can skip the solve altogether.
+const STILL: f32 = 0.0001;
+
+// A steady breeze across the map on top of whatever the air is stirred up to, in cells
+// per tick with positive to the right. It can pick up or die away with height, changing
+// evenly from the bottom of the map to the top.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Wind {
+    pub ground: f32,
+    pub aloft: f32,
+}
+
+impl Wind {
+    pub fn constant(speed: f32) -> Wind {
+        Wind {
+            ground: speed,
+            aloft: speed,
+        }
+    }
+
+    pub fn speed_at(&self, altitude: f32) -> f32 {
+        // Altitude runs from 0 at the bottom of the map to 1 at the top.
+        self.ground + (self.aloft - self.ground) * altitude.clamp(0.0, 1.0)
+    }
+}
+
+// Wind over the map, as a coarse grid of velocities solved with a stable fluids step:
+// the air carries its own motion along, then swirls round so as much flows out of each
+// cell as in. Velocities are in map cells per tick, and positive y is up like forces.
+pub struct Air {
+    // Of the map, in map cells.
+    height: usize,
+    rows: usize,
+    columns: usize,
+    velocity_y: Vec<f32>,
+    velocity_x: Vec<f32>,
+    wind: Wind,
+}
+
+impl Air {
+    pub fn new(width: usize, height: usize) -> Air {
+        let rows = height.div_ceil(AIR_CELL);
+        let columns = width.div_ceil(AIR_CELL);
+        Air {
+            height,
+            rows,
+            columns,
+            velocity_y: vec![0.0; rows * columns],
+            velocity_x: vec![0.0; rows * columns],
+            wind: Wind::default(),
+        }
+    }
+
+    pub fn wind(&self) -> Wind {
+        self.wind
+    }
+
+    pub fn set_wind(&mut self, wind: Wind) {
+        self.wind = wind;
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn clear(&mut self) {
+        self.velocity_y.fill(0.0);
+        self.velocity_x.fill(0.0);
+    }
+
+    pub fn cell(&self, row: usize, column: usize) -> (f32, f32) {
+        if row < self.rows && column < self.columns {
+            let index = row * self.columns + column;
+            (self.velocity_y[index], self.velocity_x[index])
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    pub fn set_cell(&mut self, row: usize, column: usize, velocity_y: f32, velocity_x: f32) {
+        if row < self.rows && column < self.columns {
+            let index = row * self.columns + column;
+            self.velocity_y[index] = velocity_y;
+            self.velocity_x[index] = velocity_x;
+        }
+    }
+
+    pub fn push(&mut self, y: usize, x: usize, velocity_y: f32, velocity_x: f32) {
+        // A push on one map cell is shared across the whole air cell it's in.
+        let (row, column) = (y / AIR_CELL, x / AIR_CELL);
+        if row < self.rows && column < self.columns {
+            let index = row * self.columns + column;
+            let share = (AIR_CELL * AIR_CELL) as f32;
+            self.velocity_y[index] += velocity_y / share;
+            self.velocity_x[index] += velocity_x / share;
+        }
+    }
+
+    pub fn velocity_at(&self, y: f32, x: f32) -> (f32, f32) {
+        // Blended between the four nearest air cells so the wind doesn't jump at their
+        // edges, plus the breeze at that height. Takes map coordinates.
+        let row = y / AIR_CELL as f32 - 0.5;
+        let column = x / AIR_CELL as f32 - 0.5;
+        let altitude = 1.0 - y / self.height as f32;
+        (
+            sample(&self.velocity_y, self.rows, self.columns, row, column),
+            sample(&self.velocity_x, self.rows, self.columns, row, column)
+                + self.wind.speed_at(altitude),
+        )
+    }
+
+    pub fn step(&mut self) {
+        let mut velocities = self.velocity_y.iter().chain(&self.velocity_x);
+        if velocities.all(|&velocity| velocity == 0.0) {
+            return;
+        }
+        self.advect();
+        self.project();
+        for velocity in self.velocity_y.iter_mut().chain(self.velocity_x.iter_mut()) {
+            *velocity *= RETENTION;
+            if velocity.abs() < STILL {
+                *velocity = 0.0;
+            }
+        }
+    }
+
+    fn advect(&mut self) {
+        // Each cell takes whatever air was upwind of it a tick ago.
+        let velocity_y = self.velocity_y.clone();
+        let velocity_x = self.velocity_x.clone();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let index = row * self.columns + column;
+                let from_row = row as f32 + velocity_y[index] / AIR_CELL as f32;
+                let from_column = column as f32 - velocity_x[index] / AIR_CELL as f32;
+                self.velocity_y[index] =
+                    sample(&velocity_y, self.rows, self.columns, from_row, from_column);
+                self.velocity_x[index] =
+                    sample(&velocity_x, self.rows, self.columns, from_row, from_column);
+            }
+        }
+    }
+
+    fn project(&mut self) {
+        // Work out how much more air leaves each cell than arrives, find the pressure
+        // that would even that out, and take its slope off the velocity. The air past
+        // the edge of the map is still and at rest, so wind can blow off the map.
+        let (rows, columns) = (self.rows, self.columns);
+        let at = |values: &[f32], row: isize, column: isize| {
+            if row < 0 || column < 0 || row as usize >= rows || column as usize >= columns {
+                0.0
+            } else {
+                values[row as usize * columns + column as usize]
+            }
+        };
+        let mut divergence = vec![0.0; rows * columns];
+        for row in 0..rows as isize {
+            for column in 0..columns as isize {
+                // Rows count downwards, so upward velocity flows towards the row above.
+                let outflow_x =
+                    at(&self.velocity_x, row, column + 1) - at(&self.velocity_x, row, column - 1);
+                let outflow_y =
+                    at(&self.velocity_y, row - 1, column) - at(&self.velocity_y, row + 1, column);
+                divergence[row as usize * columns + column as usize] =
+                    (outflow_x + outflow_y) / 2.0;
+            }
+        }
+        let mut pressure = vec![0.0; rows * columns];
+        for _ in 0..ITERATIONS {
+            for row in 0..rows as isize {
+                for column in 0..columns as isize {
+                    let neighbours = at(&pressure, row - 1, column)
+                        + at(&pressure, row + 1, column)
+                        + at(&pressure, row, column - 1)
+                        + at(&pressure, row, column + 1);
+                    let index = row as usize * columns + column as usize;
+                    pressure[index] = (neighbours - divergence[index]) / 4.0;
+                }
+            }
+        }
+        for row in 0..rows as isize {
+            for column in 0..columns as isize {
+                let index = row as usize * columns + column as usize;
+                self.velocity_x[index] -=
+                    (at(&pressure, row, column + 1) - at(&pressure, row, column - 1)) / 2.0;
+                self.velocity_y[index] -=
+                    (at(&pressure, row - 1, column) - at(&pressure, row + 1, column)) / 2.0;
+            }
+        }
+    }
+}
+
+fn sample(values: &[f32], rows: usize, columns: usize, row: f32, column: f32) -> f32 {
+    // Bilinear, clamped to the grid.
+    let row = row.clamp(0.0, (rows - 1) as f32);
+    let column = column.clamp(0.0, (columns - 1) as f32);
+    let (top, left) = (row.floor() as usize, column.floor() as usize);
+    let (bottom, right) = ((top + 1).min(rows - 1), (left + 1).min(columns - 1));
+    let (down, across) = (row - top as f32, column - left as f32);
+    let value = |r: usize, c: usize| values[r * columns + c];
+    let upper = value(top, left) * (1.0 - across) + value(top, right) * across;
+    let lower = value(bottom, left) * (1.0 - across) + value(bottom, right) * across;
+    upper * (1.0 - down) + lower * down
+}
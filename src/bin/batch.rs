@@ -0,0 +1,348 @@
+// Runs firework designs headlessly and writes out how each one performed.
+//
+//   firework_batch [--ticks N] [--seed S] [--wind SPEED[,ALOFT]] [--materials FILE]
+//                  [--palette FILE] [--out DIR] [--export gif|png|y4m] [--every N]
+//                  DESIGN...
+//
+// A design is a scene file, or a PNG that's read through the palette. For every
+// design the output directory gets <name>.png with the final state and
+// <name>.counts.csv with the material counts after every tick, except that the
+// pressure column is the total pressure in the air. summary.csv gets one line per
+// design. With --export, every Nth tick is also written to <name>.gif, <name>.y4m
+// or the <name>_frames directory. --wind blows across every design in cells per
+// tick, changing evenly with height to ALOFT at the top if given, in place of any
+// wind the scene had.
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use firework_engineer::air::Wind;
+use firework_engineer::export::{ExportFormat, FrameExporter};
+use firework_engineer::image_io::{self, Palette};
+use firework_engineer::material::Material;
+use firework_engineer::material_map::MaterialMap;
+use firework_engineer::material_registry::{self, MaterialRegistry};
+use firework_engineer::scene;
+use firework_engineer::simulation_engine::SimulationEngine;
+
+struct Options {
+    ticks: u64,
+    seed: Option<u64>,
+    wind: Option<Wind>,
+    palette: Palette,
+    out: PathBuf,
+    export: Option<ExportFormat>,
+    every: u64,
+    designs: Vec<PathBuf>,
+}
+
+struct Summary {
+    initial_cells: usize,
+    final_cells: usize,
+    peak_fire: usize,
+    peak_fire_tick: u64,
+    first_fire_tick: Option<u64>,
+    last_fire_tick: Option<u64>,
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: firework_batch [--ticks N] [--seed S] [--wind SPEED[,ALOFT]] \
+                 [--materials FILE] [--palette FILE] [--out DIR] [--export gif|png|y4m] \
+                 [--every N] DESIGN..."
+            );
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = fs::create_dir_all(&options.out) {
+        eprintln!("{}: {}", options.out.display(), e);
+        std::process::exit(1);
+    }
+
+    let mut failed = false;
+    let mut summaries = Vec::new();
+    for design in &options.designs {
+        let started = Instant::now();
+        match run(design, &options) {
+            Ok((engine, summary)) => {
+                println!(
+                    "{}: {} ticks in {:.1}s, {} -> {} cells, peak fire {} at tick {}",
+                    design.display(),
+                    options.ticks,
+                    started.elapsed().as_secs_f64(),
+                    summary.initial_cells,
+                    summary.final_cells,
+                    summary.peak_fire,
+                    summary.peak_fire_tick
+                );
+                summaries.push((design.clone(), engine.seed(), summary));
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+
+    if let Err(e) = write_summary(&options.out.join("summary.csv"), options.ticks, &summaries) {
+        eprintln!("{}", e);
+        failed = true;
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        ticks: 1000,
+        seed: None,
+        wind: None,
+        palette: Palette::empty(),
+        out: PathBuf::from("batch_output"),
+        export: None,
+        every: 1,
+        designs: Vec::new(),
+    };
+    let mut palette = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "--ticks" => {
+                options.ticks = value("--ticks")?
+                    .parse()
+                    .map_err(|_| "--ticks must be an unsigned integer".to_string())?
+            }
+            "--seed" => {
+                options.seed = Some(
+                    value("--seed")?
+                        .parse()
+                        .map_err(|_| "--seed must be an unsigned integer".to_string())?,
+                )
+            }
+            "--wind" => options.wind = Some(parse_wind(&value("--wind")?)?),
+            "--materials" => {
+                let registry = MaterialRegistry::load(Path::new(&value("--materials")?))?;
+                material_registry::install(registry)?;
+            }
+            "--palette" => palette = Some(value("--palette")?),
+            "--out" => options.out = PathBuf::from(value("--out")?),
+            "--export" => {
+                options.export = Some(match value("--export")?.as_str() {
+                    "gif" => ExportFormat::Gif,
+                    "png" => ExportFormat::PngSequence,
+                    "y4m" => ExportFormat::Y4m,
+                    other => return Err(format!("Unknown export format {}", other)),
+                })
+            }
+            "--every" => {
+                options.every = value("--every")?
+                    .parse()
+                    .ok()
+                    .filter(|every| *every > 0)
+                    .ok_or_else(|| "--every must be a positive integer".to_string())?
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => options.designs.push(PathBuf::from(arg)),
+        }
+    }
+    if options.designs.is_empty() {
+        return Err("No designs given".to_string());
+    }
+    // Read after --materials is installed, so palette names resolve against it.
+    options.palette = match palette {
+        Some(path) => Palette::load(Path::new(&path))?,
+        None => Palette::default(),
+    };
+    Ok(options)
+}
+
+fn parse_wind(value: &str) -> Result<Wind, String> {
+    let error = || {
+        format!(
+            "--wind must be a speed or two speeds like 1.5,3, not {}",
+            value
+        )
+    };
+    let mut speeds = value.split(',').map(|speed| speed.parse::<f32>());
+    let ground = speeds.next().and_then(Result::ok).ok_or_else(error)?;
+    let aloft = match speeds.next() {
+        Some(aloft) => aloft.map_err(|_| error())?,
+        None => ground,
+    };
+    if speeds.next().is_some() {
+        return Err(error());
+    }
+    Ok(Wind { ground, aloft })
+}
+
+fn load_design(design: &Path, options: &Options) -> Result<SimulationEngine, String> {
+    let is_png = design
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+    if is_png {
+        let (map, unknown) = image_io::import_png(design, &options.palette)?;
+        for pixel in &unknown {
+            eprintln!(
+                "{}: unknown colour {:?} at (Y, X) ({}, {})",
+                design.display(),
+                pixel.rgb,
+                pixel.y,
+                pixel.x
+            );
+        }
+        let mut engine = match options.seed {
+            Some(seed) => SimulationEngine::with_seed(map.width(), map.height(), seed),
+            None => SimulationEngine::new(map.width(), map.height()),
+        };
+        engine.replace_map(map);
+        if let Some(wind) = options.wind {
+            engine.set_wind(wind);
+        }
+        Ok(engine)
+    } else {
+        let mut engine = scene::load(design)?;
+        if let Some(seed) = options.seed {
+            engine.reseed(seed);
+        }
+        if let Some(wind) = options.wind {
+            engine.set_wind(wind);
+        }
+        Ok(engine)
+    }
+}
+
+fn run(design: &Path, options: &Options) -> Result<(SimulationEngine, Summary), String> {
+    let mut engine = load_design(design, options)?;
+    let name = design
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "design".to_string());
+
+    let counts_path = options.out.join(format!("{}.counts.csv", name));
+    let io_error = |e: std::io::Error| format!("{}: {}", counts_path.display(), e);
+    let mut counts_file = BufWriter::new(File::create(&counts_path).map_err(io_error)?);
+    write!(counts_file, "tick").map_err(io_error)?;
+    let materials = Material::all();
+    for material in &materials {
+        write!(counts_file, ",{}", material.name()).map_err(io_error)?;
+    }
+    writeln!(counts_file).map_err(io_error)?;
+
+    let fire_index = materials
+        .iter()
+        .position(|material| matches!(material, Material::Fire { .. }))
+        .expect("Fire is a material");
+    let initial_counts = material_counts(engine.map(), materials.len());
+    let mut summary = Summary {
+        initial_cells: initial_counts.iter().sum(),
+        final_cells: initial_counts.iter().sum(),
+        peak_fire: 0,
+        peak_fire_tick: engine.tick(),
+        first_fire_tick: None,
+        last_fire_tick: None,
+    };
+
+    if let Some(format) = options.export {
+        let path = match format {
+            ExportFormat::Gif => options.out.join(format!("{}.gif", name)),
+            ExportFormat::PngSequence => options.out.join(format!("{}_frames", name)),
+            ExportFormat::Y4m => options.out.join(format!("{}.y4m", name)),
+        };
+        let exporter = FrameExporter::create(
+            &path,
+            format,
+            engine.width(),
+            engine.height(),
+            options.every,
+        )?;
+        engine.start_export(exporter);
+    }
+
+    for _ in 0..options.ticks {
+        engine.step();
+        let counts = material_counts(engine.map(), materials.len());
+        write!(counts_file, "{}", engine.tick()).map_err(io_error)?;
+        let pressure = engine.map().pressure().total();
+        for (material, count) in materials.iter().zip(&counts) {
+            match material {
+                Material::Pressure { .. } => write!(counts_file, ",{}", pressure),
+                _ => write!(counts_file, ",{}", count),
+            }
+            .map_err(io_error)?;
+        }
+        writeln!(counts_file).map_err(io_error)?;
+
+        let fire = counts[fire_index];
+        if fire > 0 {
+            summary.first_fire_tick.get_or_insert(engine.tick());
+            summary.last_fire_tick = Some(engine.tick());
+        }
+        if fire > summary.peak_fire {
+            summary.peak_fire = fire;
+            summary.peak_fire_tick = engine.tick();
+        }
+        summary.final_cells = counts.iter().sum();
+    }
+    counts_file.flush().map_err(io_error)?;
+    if let Some(result) = engine.stop_export() {
+        result?;
+    } else if options.export.is_some() {
+        return Err(format!("{}: frame export failed", design.display()));
+    }
+
+    image_io::export_png(engine.map(), &options.out.join(format!("{}.png", name)))?;
+    Ok((engine, summary))
+}
+
+fn material_counts(map: &MaterialMap, materials: usize) -> Vec<usize> {
+    // Cells of each material, in registry order.
+    let mut counts = vec![0; materials];
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            if let Some(record) = map.contents_at_index(y, x) {
+                counts[record.mat.id().0 as usize] += 1;
+            }
+        }
+    }
+    counts
+}
+
+fn write_summary(
+    path: &Path,
+    ticks: u64,
+    summaries: &[(PathBuf, u64, Summary)],
+) -> Result<(), String> {
+    let io_error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    let mut file = BufWriter::new(File::create(path).map_err(io_error)?);
+    writeln!(
+        file,
+        "design,seed,ticks,initial_cells,final_cells,peak_fire,peak_fire_tick,first_fire_tick,last_fire_tick"
+    )
+    .map_err(io_error)?;
+    let tick_or_blank = |tick: Option<u64>| tick.map(|t| t.to_string()).unwrap_or_default();
+    for (design, seed, summary) in summaries {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{}",
+            design.display(),
+            seed,
+            ticks,
+            summary.initial_cells,
+            summary.final_cells,
+            summary.peak_fire,
+            summary.peak_fire_tick,
+            tick_or_blank(summary.first_fire_tick),
+            tick_or_blank(summary.last_fire_tick)
+        )
+        .map_err(io_error)?;
+    }
+    file.flush().map_err(io_error)
+}
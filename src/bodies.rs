@@ -1,60 +1,61 @@
-use crate::material::Material;
 use crate::material_map::MaterialMap;
-use std::collections::HashSet;
 
-pub fn find_bodies(map: &MaterialMap, height: usize, width: usize) -> Vec<HashSet<(usize, usize)>> {
-    let mut bodies: Vec<HashSet<(usize, usize)>> = Vec::new();
+pub fn find_bodies(map: &MaterialMap, height: usize, width: usize) -> Vec<Vec<(usize, usize)>> {
+    // Flood fill touching cells into bodies. Only solids stick together; powder grains
+    // and liquid drops are each a body of their own.
+    let mut visited = vec![false; height * width];
+    let mut bodies: Vec<Vec<(usize, usize)>> = Vec::new();
+    let joins_body = |y: usize, x: usize| match map.contents_at_index(y, x) {
+        // Massless materials shouldn't be part of bodies, and fixed ones like the mortar
+        // tube never move
+        Some(contents) => contents.mat.density() > 0.0 && !contents.mat.substance().fixed,
+        None => false, // Nothing to be in body
+    };
+    let is_rigid = |y: usize, x: usize| {
+        map.contents_at_index(y, x)
+            .is_some_and(|contents| contents.mat.state().is_rigid())
+    };
+    let fragment = |y: usize, x: usize| map.contents_at_index(y, x).map(|c| c.fragment);
+
     for y in 0..height {
+        if map.row_is_empty(y) {
+            continue;
+        }
         for x in 0..width {
-            if !map.something_at_index(y, x) {
-                continue; // Nothing to be in body
-            }
-            let contents = map.contents_at_index(y, x).unwrap();
-            if contents.mat == Material::Pressure {
-                continue; // Pressure shouldn't be part of bodies
+            if visited[y * width + x] || !joins_body(y, x) {
+                continue;
             }
-            let mut found_left = false;
-            let mut left_index = 0;
-            let mut found_below = false;
-            let mut below_index = 0;
-            for i in 0..bodies.len() {
-                if x > 0 && bodies[i].contains(&(y, x - 1)) {
-                    found_left = true;
-                    left_index = i;
-                }
-                if y > 0 && bodies[i].contains(&(y - 1, x)) {
-                    found_below = true;
-                    below_index = i;
-                }
+            visited[y * width + x] = true;
+            if !is_rigid(y, x) {
+                bodies.push(vec![(y, x)]);
+                continue;
             }
-            if !found_left && !found_below {
-                // New body starting
-                let mut body = HashSet::new();
-                body.insert((y, x));
-                bodies.push(body);
-            } else if found_left && !found_below {
-                bodies[left_index].insert((y, x));
-            } else if found_below && !found_left {
-                bodies[below_index].insert((y, x));
-            } else {
-                if left_index == below_index {
-                    // Both bodies are the same.
-                    bodies[left_index].insert((y, x));
-                } else if left_index < below_index {
-                    // found in two different bodies, merge them.
-                    bodies[left_index].insert((y, x));
-                    for coord in bodies[below_index].clone() {
-                        bodies[left_index].insert(coord);
+
+            let mut body = Vec::new();
+            let mut stack = vec![(y, x)];
+            while let Some((cy, cx)) = stack.pop() {
+                body.push((cy, cx));
+                let neighbours = [
+                    (cy.wrapping_sub(1), cx),
+                    (cy + 1, cx),
+                    (cy, cx.wrapping_sub(1)),
+                    (cy, cx + 1),
+                ];
+                for (ny, nx) in neighbours {
+                    if ny >= height || nx >= width || visited[ny * width + nx] {
+                        continue;
                     }
-                    bodies.remove(below_index);
-                } else {
-                    bodies[below_index].insert((y, x));
-                    for coord in bodies[left_index].clone() {
-                        bodies[below_index].insert(coord);
+                    // Pieces of a ruptured body touch but no longer hold together
+                    if joins_body(ny, nx)
+                        && is_rigid(ny, nx)
+                        && fragment(ny, nx) == fragment(cy, cx)
+                    {
+                        visited[ny * width + nx] = true;
+                        stack.push((ny, nx));
                     }
-                    bodies.remove(left_index);
                 }
             }
+            bodies.push(body);
         }
     }
 
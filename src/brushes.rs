@@ -1,7 +1,7 @@
-use rand;
 use rand::Rng;
 
-pub fn circle(
+pub fn circle<R: Rng>(
+    rng: &mut R,
     r: f32,
     y: i32,
     x: i32,
@@ -17,7 +17,7 @@ pub fn circle(
     for _y in (y - _r)..(y + _r) {
         let b = (r.powi(2) - ((y - _y) as f32).powi(2)).sqrt().floor() as i32;
         for _x in (x - b)..(x + b) {
-            if rand::thread_rng().gen_range(0f32..1.) > opacity
+            if rng.gen_range(0f32..1.) > opacity
                 && _x >= 0
                 && _y >= 0
                 && _x < uwidth
@@ -28,5 +28,5 @@ pub fn circle(
         }
     }
 
-    return v;
+    v
 }
@@ -3,17 +3,28 @@ use crate::material::Material;
 #[derive(Copy, Clone, Debug)]
 pub struct MaterialRecord {
     pub mat: Material,
-    pub force_y: i8,
-    pub force_x: i8,
+    // Forces gathered this tick. Positive y is up.
+    pub force_y: f32,
+    pub force_x: f32,
+    // Cells per tick, kept from one tick to the next so bodies can coast. Positive y is up.
+    pub velocity_y: f32,
+    pub velocity_x: f32,
+    // How far it's got towards the next cell from moves too short to take it all the
+    // way, so slow bodies still creep along. Positive y is up.
+    pub offset_y: f32,
+    pub offset_x: f32,
+    // Soaked by a neighbouring liquid; damp cells are much harder to ignite.
+    pub damp: bool,
+    // Ticks since the cell appeared, for materials with a lifetime.
+    pub age: u16,
+    // Degrees Celsius. Conducts to touching cells and ignites flammable ones.
+    pub temperature: f32,
+    // Which piece of a ruptured body this cell belongs to; 0 if it never broke.
+    // Solids only stick to touching cells of the same fragment.
+    pub fragment: u32,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct Cell {
-    pub contents: Option<MaterialRecord>
-}
-
-impl Cell {
-    pub fn default() -> Cell {
-        Cell { contents: None }
-    }
+    pub contents: Option<MaterialRecord>,
 }
@@ -1,25 +1,23 @@
-use time;
-
+// Measures simulation time in ticks rather than wall time, so timers behave
+// the same however fast the machine steps the simulation.
 pub struct Counter {
-    pub counter: time::Instant,
+    pub counter: u64,
 }
 
 impl Counter {
-    pub fn new() -> Counter {
-        Counter {
-            counter: time::Instant::now(),
-        }
+    pub fn new(tick: u64) -> Counter {
+        Counter { counter: tick }
     }
 
-    pub fn elapsed(&self) -> time::Duration {
-        time::Instant::now() - self.counter
+    pub fn elapsed(&self, tick: u64) -> u64 {
+        tick.saturating_sub(self.counter)
     }
 
-    pub fn reset(&mut self) {
-        self.counter = time::Instant::now();
+    pub fn reset(&mut self, tick: u64) {
+        self.counter = tick;
     }
 
-    pub fn elapsed_gt(&self, msecs: i64) -> bool {
-        self.elapsed() >= time::Duration::milliseconds(msecs)
+    pub fn elapsed_gt(&self, tick: u64, ticks: u64) -> bool {
+        self.elapsed(tick) >= ticks
     }
 }
@@ -0,0 +1,178 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::image_io;
+use crate::simulation_engine::TICK_DURATION;
+
+// Where the GUI's capture hotkey writes.
+pub const QUICKSAVE_PATH: &str = "firework.gif";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Gif,
+    // One numbered PNG per frame in a directory.
+    PngSequence,
+    // Uncompressed YUV 4:4:4 video, readable by ffmpeg and most players.
+    Y4m,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> ExportFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => ExportFormat::Gif,
+            Some(extension) if extension.eq_ignore_ascii_case("y4m") => ExportFormat::Y4m,
+            _ => ExportFormat::PngSequence,
+        }
+    }
+}
+
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>),
+    PngSequence(PathBuf),
+    Y4m(BufWriter<File>),
+}
+
+// Writes the rendered pixel buffer out every `every` ticks.
+pub struct FrameExporter {
+    output: Output,
+    path: PathBuf,
+    width: usize,
+    height: usize,
+    every: u64,
+    frames: u64,
+    last_tick: Option<u64>,
+}
+
+impl FrameExporter {
+    pub fn create(
+        path: &Path,
+        format: ExportFormat,
+        width: usize,
+        height: usize,
+        every: u64,
+    ) -> Result<FrameExporter, String> {
+        let every = every.max(1);
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let output = match format {
+            ExportFormat::Gif => {
+                if width > u16::MAX as usize || height > u16::MAX as usize {
+                    return Err(error(&"too large for a GIF"));
+                }
+                let file = File::create(path).map_err(|e| error(&e))?;
+                let mut encoder =
+                    gif::Encoder::new(BufWriter::new(file), width as u16, height as u16, &[])
+                        .map_err(|e| error(&e))?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(|e| error(&e))?;
+                Output::Gif(encoder)
+            }
+            ExportFormat::PngSequence => {
+                fs::create_dir_all(path).map_err(|e| error(&e))?;
+                Output::PngSequence(path.to_path_buf())
+            }
+            ExportFormat::Y4m => {
+                let file = File::create(path).map_err(|e| error(&e))?;
+                let mut writer = BufWriter::new(file);
+                // Frame rate as a fraction: one frame per `every` ticks.
+                let ticks_per_second = 1_000_000 / TICK_DURATION.as_micros() as u64;
+                writeln!(
+                    writer,
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                    width, height, ticks_per_second, every
+                )
+                .map_err(|e| error(&e))?;
+                Output::Y4m(writer)
+            }
+        };
+        Ok(FrameExporter {
+            output,
+            path: path.to_path_buf(),
+            width,
+            height,
+            every,
+            frames: 0,
+            last_tick: None,
+        })
+    }
+
+    pub fn every(&self) -> u64 {
+        self.every
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn wants(&self, tick: u64) -> bool {
+        self.last_tick
+            .is_none_or(|last_tick| tick >= last_tick + self.every)
+    }
+
+    pub fn write_frame(&mut self, tick: u64, rgb: &[u8]) -> Result<(), String> {
+        // `rgb` is an RGB24 buffer the size given to create(), like the engine's pixel buffer.
+        let path = &self.path;
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        if rgb.len() != self.width * self.height * 3 {
+            return Err(error(&"frame size changed during export"));
+        }
+        match &mut self.output {
+            Output::Gif(encoder) => {
+                let mut frame =
+                    gif::Frame::from_rgb_speed(self.width as u16, self.height as u16, rgb, 10);
+                // GIF delays are in hundredths of a second.
+                frame.delay = (TICK_DURATION.as_millis() as u64 * self.every / 10)
+                    .clamp(1, u16::MAX as u64) as u16;
+                encoder.write_frame(&frame).map_err(|e| error(&e))?;
+            }
+            Output::PngSequence(directory) => {
+                let frame_path = directory.join(format!("frame_{:06}.png", self.frames));
+                let file = File::create(&frame_path)
+                    .map_err(|e| format!("{}: {}", frame_path.display(), e))?;
+                image_io::write_png(BufWriter::new(file), self.width, self.height, rgb)
+                    .map_err(|e| format!("{}: {}", frame_path.display(), e))?;
+            }
+            Output::Y4m(writer) => {
+                writeln!(writer, "FRAME").map_err(|e| error(&e))?;
+                let pixels = rgb.chunks_exact(3);
+                let y: Vec<u8> = pixels.clone().map(|p| yuv(p).0).collect();
+                let u: Vec<u8> = pixels.clone().map(|p| yuv(p).1).collect();
+                let v: Vec<u8> = pixels.map(|p| yuv(p).2).collect();
+                for plane in [&y, &u, &v] {
+                    writer.write_all(plane).map_err(|e| error(&e))?;
+                }
+            }
+        }
+        self.frames += 1;
+        self.last_tick = Some(tick);
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<u64, String> {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", self.path.display(), e);
+        match self.output {
+            Output::Gif(encoder) => {
+                encoder
+                    .into_inner()
+                    .map_err(|e| error(&e))?
+                    .flush()
+                    .map_err(|e| error(&e))?;
+            }
+            Output::PngSequence(_) => {}
+            Output::Y4m(mut writer) => writer.flush().map_err(|e| error(&e))?,
+        }
+        Ok(self.frames)
+    }
+}
+
+fn yuv(rgb: &[u8]) -> (u8, u8, u8) {
+    // BT.601 studio swing, which is what Y4M readers assume.
+    let r = rgb[0] as f32;
+    let g = rgb[1] as f32;
+    let b = rgb[2] as f32;
+    let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+    let u = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+    let v = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+    (y.round() as u8, u.round() as u8, v.round() as u8)
+}
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::material::Material;
+use crate::material_map::MaterialMap;
+use crate::material_registry::registry;
+use crate::scene;
+
+// Where the PNG hotkeys read and write.
+pub const QUICKSAVE_PATH: &str = "firework.png";
+
+// Maps pixel colours to materials. Black, and fully transparent pixels, are empty space.
+pub struct Palette {
+    entries: Vec<([u8; 3], Material)>,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        // Every material's own colour, so exported maps load back unchanged. Flame
+        // colours come first so they never shadow a material's own colour.
+        let mut palette = Palette::empty();
+        for id in registry().ids() {
+            if let Some(colour) = registry().get(id).flame_colour {
+                palette.insert(colour, Material::ignite(id));
+            }
+        }
+        for material in Material::all() {
+            let rgb = material.rgb();
+            palette.insert([rgb.red as u8, rgb.green as u8, rgb.blue as u8], material);
+        }
+        palette
+    }
+}
+
+impl Palette {
+    pub fn empty() -> Palette {
+        Palette {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, rgb: [u8; 3], material: Material) {
+        // A later entry for the same colour replaces the earlier one.
+        self.entries.retain(|entry| entry.0 != rgb);
+        self.entries.push((rgb, material));
+    }
+
+    pub fn material(&self, rgb: [u8; 3]) -> Option<Material> {
+        self.entries
+            .iter()
+            .find(|entry| entry.0 == rgb)
+            .map(|entry| entry.1)
+    }
+
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        // One entry per line: `RRGGBB material`, with `fire` taking `duration pressure`.
+        // Blank lines and lines starting with `#` are ignored.
+        let mut palette = Palette::empty();
+        for (number, line) in text.lines().enumerate() {
+            let line_number = number + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let rgb = scene::parse_hex_colour(fields.next(), line_number)?;
+            let material = scene::parse_material(&mut fields, line_number)?;
+            palette.insert(rgb, material);
+        }
+        Ok(palette)
+    }
+
+    pub fn load(path: &Path) -> Result<Palette, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Palette::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct UnknownColour {
+    pub y: usize,
+    pub x: usize,
+    pub rgb: [u8; 3],
+}
+
+pub fn import_png(
+    path: &Path,
+    palette: &Palette,
+) -> Result<(MaterialMap, Vec<UnknownColour>), String> {
+    // Pixels whose colour isn't in the palette are left empty and handed back so the
+    // caller can point at them.
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut data)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let channels = info.color_type.samples();
+    let mut map = MaterialMap::new(width, height);
+    let mut unknown = Vec::new();
+    for y in 0..height {
+        let row = &data[y * info.line_size..];
+        for x in 0..width {
+            let pixel = &row[x * channels..(x + 1) * channels];
+            let (rgb, alpha) = match info.color_type {
+                png::ColorType::Grayscale => ([pixel[0]; 3], 255),
+                png::ColorType::GrayscaleAlpha => ([pixel[0]; 3], pixel[1]),
+                png::ColorType::Rgb => ([pixel[0], pixel[1], pixel[2]], 255),
+                png::ColorType::Rgba => ([pixel[0], pixel[1], pixel[2]], pixel[3]),
+                png::ColorType::Indexed => {
+                    return Err(format!("{}: unexpanded palette image", path.display()))
+                }
+            };
+            if alpha == 0 || rgb == [0, 0, 0] {
+                continue;
+            }
+            match palette.material(rgb) {
+                Some(material) => map.add_material(y, x, material),
+                None => unknown.push(UnknownColour { y, x, rgb }),
+            }
+        }
+    }
+    Ok((map, unknown))
+}
+
+pub fn export_png(map: &MaterialMap, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    write_png(
+        BufWriter::new(file),
+        map.width(),
+        map.height(),
+        &rgb_pixels(map),
+    )
+    .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn rgb_pixels(map: &MaterialMap) -> Vec<u8> {
+    // RGB24 rendering of the map with empty cells left black.
+    let mut data = vec![0; map.width() * map.height() * 3];
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            if let Some(record) = map.contents_at_index(y, x) {
+                let offset = (y * map.width() + x) * 3;
+                let rgb = record.mat.rgb();
+                data[offset] = rgb.red as u8;
+                data[offset + 1] = rgb.green as u8;
+                data[offset + 2] = rgb.blue as u8;
+            }
+        }
+    }
+    data
+}
+
+pub fn write_png<W: std::io::Write>(
+    w: W,
+    width: usize,
+    height: usize,
+    rgb: &[u8],
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(w, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    writer.finish()
+}
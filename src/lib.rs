@@ -1,8 +1,16 @@
+pub mod air;
 pub mod bodies;
 pub mod brushes;
 pub mod cell;
 pub mod counter;
+pub mod export;
+pub mod image_io;
 pub mod material;
 pub mod material_map;
+pub mod material_registry;
+pub mod particles;
+pub mod pressure_field;
+pub mod replay;
+pub mod scene;
 pub mod simulation_engine;
 pub mod window;
@@ -1,12 +1,14 @@
-extern crate rand;
 extern crate sdl2;
-extern crate time;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
 
+use firework_engineer::material_registry::{self, MaterialRegistry};
 use firework_engineer::simulation_engine::SimulationEngine;
 use firework_engineer::window;
 
@@ -29,10 +31,7 @@ pub fn main() {
         .build()
         .expect("Could not make a canvas");
     canvas
-        .set_logical_size(
-            window::SCREEN_WIDTH as u32,
-            window::SCREEN_HEIGHT as u32,
-        )
+        .set_logical_size(window::SCREEN_WIDTH as u32, window::SCREEN_HEIGHT as u32)
         .unwrap();
     let texture_creator = canvas.texture_creator();
 
@@ -44,12 +43,33 @@ pub fn main() {
         )
         .map_err(|e| e.to_string())
         .unwrap();
-    let mut simulation_engine = SimulationEngine::new(
-        window::SCREEN_WIDTH as usize,
-        window::SCREEN_HEIGHT as usize,
-    );
+    // firework_engineer [--materials FILE] [SEED]
+    // Pass a seed to reproduce an earlier run.
+    let mut seed = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--materials" {
+            let path = args.next().expect("--materials needs a file");
+            let registry = MaterialRegistry::load(Path::new(&path)).unwrap();
+            material_registry::install(registry).unwrap();
+        } else {
+            seed = Some(arg.parse().expect("Seed must be an unsigned integer"));
+        }
+    }
+    let mut simulation_engine = match seed {
+        Some(seed) => {
+            SimulationEngine::with_seed(window::SCREEN_WIDTH, window::SCREEN_HEIGHT, seed)
+        }
+        None => SimulationEngine::new(window::SCREEN_WIDTH, window::SCREEN_HEIGHT),
+    };
+    println!("Seed {}", simulation_engine.seed());
     let mut event_pump = sdl_context.event_pump().unwrap();
 
+    let mut time_at_last_render = Instant::now();
+    let mut elapsed = Duration::from_secs(0);
+    let mut frame_counter = 0;
+    let mut update_counter = 0;
+
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
@@ -62,7 +82,21 @@ pub fn main() {
             }
         }
 
-        simulation_engine.update(&mut texture);
+        let time_between_render = time_at_last_render.elapsed();
+        time_at_last_render = Instant::now();
+        update_counter += simulation_engine.advance(time_between_render);
+
+        simulation_engine.update_texture(&mut texture);
+
+        frame_counter += 1;
+        elapsed += time_between_render;
+
+        if elapsed > Duration::from_secs(1) {
+            println!("FPS {} - Updates/Second {}", frame_counter, update_counter);
+            frame_counter = 0;
+            update_counter = 0;
+            elapsed = Duration::from_secs(0);
+        }
 
         canvas.clear();
         canvas
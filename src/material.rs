@@ -1,4 +1,6 @@
-#[derive(Clone, Debug)]
+use crate::material_registry::{registry, MaterialDef, MaterialId, State};
+
+#[derive(Clone, Copy, Debug)]
 pub struct RGB {
     pub red: usize,
     pub green: usize,
@@ -7,62 +9,130 @@ pub struct RGB {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Material {
-    Sand,
-    Explosive,
-    // Fire duration is the amount of updates it has until it's extinguished.
-    Fire { duration: i16, pressure: i8 },
-    Pressure,
-    Wood,
-    Cardboard,
+    // Anything defined in the material registry that isn't burning.
+    Compound(MaterialId),
+    // Fire duration is the amount of ticks it has until it's extinguished. Fuel is
+    // whatever caught fire, which decides what it leaves behind. Front is how many
+    // cells past this one fire has run along a fuse.
+    Fire {
+        duration: i16,
+        pressure: i8,
+        fuel: MaterialId,
+        front: f32,
+    },
+    // Strength is how hard it pushes everything around it.
+    Pressure {
+        strength: i8,
+    },
 }
 
 impl Material {
-    pub fn rgb(&self) -> RGB {
+    pub fn all() -> Vec<Material> {
+        // Every material in registry order, as the brush would paint it.
+        registry().ids().map(Material::from_id).collect()
+    }
+
+    pub fn default_material() -> Material {
+        Material::from_id(MaterialId(0))
+    }
+
+    pub fn from_id(id: MaterialId) -> Material {
+        let registry = registry();
+        if id == registry.fire() {
+            let def = registry.get(id);
+            Material::Fire {
+                duration: def.burn_duration,
+                pressure: def.pressure_yield,
+                fuel: id,
+                front: 0.0,
+            }
+        } else if id == registry.pressure() {
+            Material::Pressure {
+                strength: registry.get(id).pressure_yield,
+            }
+        } else {
+            Material::Compound(id)
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Material> {
+        registry().find(name).map(Material::from_id)
+    }
+
+    pub fn from_key(key: &str) -> Option<Material> {
+        registry()
+            .ids()
+            .find(|id| {
+                registry()
+                    .get(*id)
+                    .key
+                    .as_deref()
+                    .is_some_and(|k| k.eq_ignore_ascii_case(key))
+            })
+            .map(Material::from_id)
+    }
+
+    pub fn id(&self) -> MaterialId {
         match *self {
-            Material::Sand => RGB {
-                red: 255,
-                green: 255,
-                blue: 255,
-            },
-            Material::Explosive => RGB {
-                red: 255,
-                green: 255,
-                blue: 0,
-            },
-            Material::Fire { .. } => RGB {
-                red: 255,
-                green: 0,
-                blue: 0,
-            },
-            Material::Pressure => RGB {
-                red: 128,
-                green: 128,
-                blue: 128,
-            },
-            Material::Wood => RGB {
-                red: 94,
-                green: 59,
-                blue: 19,
-            },
-            Material::Cardboard => RGB {
-                red: 205,
-                green: 159,
-                blue: 97,
-            },
+            Material::Compound(id) => id,
+            Material::Fire { .. } => registry().fire(),
+            Material::Pressure { .. } => registry().pressure(),
         }
     }
 
-    pub fn density(&self) -> i8 {
-        // Density is how susceptible a material is to a force.
-        // Higher means it will go further on a push.
-        // Don't know if we actually need this.
+    pub fn def(&self) -> &'static MaterialDef {
+        registry().get(self.id())
+    }
+
+    pub fn substance(&self) -> &'static MaterialDef {
+        // What the cell is made of. A burning cell is still whatever caught fire, so it
+        // weighs and moves like its fuel.
         match *self {
-            Material::Sand => 1,
-            Material::Explosive => 1,
-            Material::Fire { .. } => 1,
-            Material::Pressure => 0,
-            Material::Wood => 1,
-            Material::Cardboard => 1,
+            Material::Fire { fuel, .. } => registry().get(fuel),
+            _ => self.def(),
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        &self.def().name
+    }
+
+    pub fn ignite(id: MaterialId) -> Material {
+        // What a cell of this material turns into when it catches fire.
+        let def = registry().get(id);
+        Material::Fire {
+            duration: def.burn_duration,
+            pressure: def.pressure_yield,
+            fuel: id,
+            front: 0.0,
+        }
+    }
+
+    pub fn strength(&self) -> Option<f32> {
+        // Burning cells are only as strong as what's burning.
+        self.substance().strength
+    }
+
+    pub fn rgb(&self) -> RGB {
+        // Burning cells glow in the colour of whatever is burning, if it has one.
+        if let Material::Fire { fuel, .. } = *self {
+            if let Some([red, green, blue]) = registry().get(fuel).flame_colour {
+                return RGB {
+                    red: red as usize,
+                    green: green as usize,
+                    blue: blue as usize,
+                };
+            }
+        }
+        self.def().rgb()
+    }
+
+    pub fn density(&self) -> f32 {
+        // Density is how heavy a cell is. Massless cells never join bodies.
+        self.substance().density
+    }
+
+    pub fn state(&self) -> State {
+        self.substance().state
+    }
 }
@@ -3,27 +3,57 @@ use crate::cell::Cell;
 use crate::cell::MaterialRecord;
 use crate::material::Material;
 use crate::material::RGB;
-use crate::window;
+use crate::material_registry::State;
+use crate::pressure_field::PressureField;
+use crate::simulation_engine::GRAVITY;
+use rand::Rng;
+
+// How many pieces a ruptured body splits into, like slices of a pie around its middle.
+const FRAGMENTS: u32 = 8;
+// How hard anything resting on something else grips it, as a fraction of its weight.
+// Sideways pushes weaker than that don't shift it, and it slides to a stop once they end.
+const FRICTION: f32 = 0.5;
+// Widest run of a powder's slope, so the shallowest it can pile is one cell in five.
+const MAX_RUN: usize = 5;
 
 pub struct MaterialMap {
     map_width: usize,
     map_height: usize,
-    max_index: usize,
-    pub mat_map: [Cell; window::SCREEN_HEIGHT * window::SCREEN_WIDTH],
+    // Row-major, map_width * map_height cells.
+    pub mat_map: Vec<Cell>,
+    // Occupied cells in each row, so the rules can skip empty rows without looking at
+    // every cell in them. Keep it in step when filling or emptying cells.
+    row_counts: Vec<usize>,
+    // Gas pressure released by burning, which lives between the cells rather than in them.
+    pressure: PressureField,
 }
 
 impl MaterialMap {
     pub fn new(width: usize, height: usize) -> MaterialMap {
+        assert!(width > 0 && height > 0, "MaterialMap must be at least 1x1");
         MaterialMap {
             map_width: width,
             map_height: height,
-            max_index: (height - 1) * width + (width - 1),
-            mat_map: MaterialMap::new_mat_map(),
+            mat_map: MaterialMap::new_mat_map(width, height),
+            row_counts: vec![0; height],
+            pressure: PressureField::new(width, height),
         }
     }
 
-    fn new_mat_map() -> [Cell; window::SCREEN_HEIGHT * window::SCREEN_WIDTH] {
-        [Cell::default(); window::SCREEN_WIDTH * window::SCREEN_HEIGHT]
+    fn new_mat_map(width: usize, height: usize) -> Vec<Cell> {
+        vec![Cell::default(); width * height]
+    }
+
+    pub fn width(&self) -> usize {
+        self.map_width
+    }
+
+    pub fn height(&self) -> usize {
+        self.map_height
+    }
+
+    pub fn in_bounds(&self, y: usize, x: usize) -> bool {
+        y < self.map_height && x < self.map_width
     }
 
     fn index(&self, y: usize, x: usize) -> usize {
@@ -31,65 +61,74 @@ impl MaterialMap {
         y * self.map_width + x
     }
 
-    fn index_with_force(
-        &self,
-        orig_y: usize,
-        orig_x: usize,
-        force_y: i8,
-        force_x: i8,
-    ) -> usize {
-        // Convert coordinate with forces into destination index
-        let mut y = orig_y;
-        let mut x = orig_x;
-        if y > 0 && force_y > 0 {
-            y -= 1;
-        } else if force_y < 0 {
-            y += 1;
-        }
-        if x < self.map_width && force_x > 0 {
-            x += 1;
-        } else if force_x < 0 {
-            x -= 1;
-        }
-        self.index(y, x)
-    }
-
     pub fn add_material(&mut self, y: usize, x: usize, material: Material) {
+        if !self.in_bounds(y, x) {
+            return;
+        }
+        // Painting pressure adds to the field instead of taking up the cell.
+        if let Material::Pressure { strength } = material {
+            self.pressure.add(y, x, strength as f32);
+            return;
+        }
         let m = MaterialRecord {
             mat: material,
-            force_y: 0i8,
-            force_x: 0i8,
+            force_y: 0.0,
+            force_x: 0.0,
+            velocity_y: 0.0,
+            velocity_x: 0.0,
+            offset_y: 0.0,
+            offset_x: 0.0,
+            damp: false,
+            age: 0,
+            temperature: material.def().temperature,
+            fragment: 0,
         };
-        self.mat_map[self.index(y, x)].contents = Some(m);
+        let index = self.index(y, x);
+        self.set_contents(index, Some(m));
     }
 
-    pub fn add_force_at_index(&mut self, y: usize, x: usize, force_y: i8, force_x: i8) {
-        let index = self.index(y, x);
-        if index > self.max_index {
-            return;
+    fn set_contents(&mut self, index: usize, contents: Option<MaterialRecord>) {
+        let row = index / self.map_width;
+        if self.mat_map[index].contents.is_some() {
+            self.row_counts[row] -= 1;
         }
-        if let Some(i) = self.mat_map[index].contents.as_mut() {
-            i.force_y = std::cmp::min(i.force_y as i16 + force_y as i16, i8::MAX as i16) as i8;
-            i.force_x = std::cmp::min(i.force_x as i16 + force_x as i16, i8::MAX as i16) as i8;
+        if contents.is_some() {
+            self.row_counts[row] += 1;
         }
+        self.mat_map[index].contents = contents;
     }
 
-    pub fn override_force_at_index(&mut self, y: usize, x: usize, force_y: i8, force_x: i8) {
-        let index = self.index(y, x);
-        if index > self.max_index {
-            return;
+    pub fn row_is_empty(&self, y: usize) -> bool {
+        self.row_counts[y] == 0
+    }
+
+    pub fn replace_material(&mut self, y: usize, x: usize, material: Material) {
+        // Change what a cell is made of but keep its motion and heat, as when it
+        // catches fire or burns down.
+        if let Some(contents) = self.contents_mut(y, x) {
+            contents.mat = material;
         }
-        if let Some(i) = self.mat_map[index].contents.as_mut() {
-            i.force_y = force_y;
-            i.force_x = force_x;
+    }
+
+    pub fn add_force_at_index(&mut self, y: usize, x: usize, force_y: f32, force_x: f32) {
+        if let Some(contents) = self.contents_mut(y, x) {
+            contents.force_y += force_y;
+            contents.force_x += force_x;
+        }
+    }
+
+    pub fn override_force_at_index(&mut self, y: usize, x: usize, force_y: f32, force_x: f32) {
+        if let Some(contents) = self.contents_mut(y, x) {
+            contents.force_y = force_y;
+            contents.force_x = force_x;
         }
     }
 
     pub fn something_at_index(&self, y: usize, x: usize) -> bool {
-        let index = self.index(y, x);
-        if index > self.max_index {
+        if !self.in_bounds(y, x) {
             return false;
         }
+        let index = self.index(y, x);
         self.mat_map[index].contents.is_some()
     }
 
@@ -98,77 +137,439 @@ impl MaterialMap {
     }
 
     pub fn contents_at_index(&self, y: usize, x: usize) -> Option<MaterialRecord> {
-        let index = self.index(y, x);
-        if index > self.max_index {
+        if !self.in_bounds(y, x) {
             return None;
         }
+        let index = self.index(y, x);
         self.mat_map[index].contents
     }
 
+    pub fn contents_mut(&mut self, y: usize, x: usize) -> Option<&mut MaterialRecord> {
+        // For changing one field of a cell's record in place.
+        if !self.in_bounds(y, x) {
+            return None;
+        }
+        let index = self.index(y, x);
+        self.mat_map[index].contents.as_mut()
+    }
+
     pub fn rgb_at_index(&self, y: usize, x: usize) -> RGB {
         self.mat_map[self.index(y, x)].contents.unwrap().mat.rgb()
     }
 
     pub fn move_material(&mut self, yfrom: usize, xfrom: usize, yto: usize, xto: usize) {
-        {
-            let moving = &self.mat_map[yfrom * self.map_width + xfrom]
-                .contents
-                .unwrap();
-            // To should always be none
-            self.mat_map[yto * self.map_width + xto].contents = Some(*moving);
-        }
-        self.mat_map[yfrom * self.map_width + xfrom].contents = None;
+        let moving = self.mat_map[yfrom * self.map_width + xfrom]
+            .contents
+            .unwrap();
+        // To should always be none
+        self.set_contents(yto * self.map_width + xto, Some(moving));
+        self.set_contents(yfrom * self.map_width + xfrom, None);
     }
 
-    pub fn remove_at_position(&mut self, y: usize, x: usize) {
-        self.mat_map[self.index(y, x)].contents = None;
+    pub fn pressure(&self) -> &PressureField {
+        &self.pressure
     }
 
-    pub fn apply_forces(&mut self) {
-        let mut new_mat_map = MaterialMap::new_mat_map();
+    pub fn pressure_mut(&mut self) -> &mut PressureField {
+        &mut self.pressure
+    }
 
-        // Given the current forces on each object, average them all then override each
-        // pixel's force with the average. This way we can get bodies to move together.
-        let bodies = bodies::find_bodies(&self, self.map_height, self.map_width);
+    pub fn is_open(&self, y: usize, x: usize) -> bool {
+        self.in_bounds(y, x) && lets_gas_through(self.mat_map[self.index(y, x)].contents)
+    }
 
-        for body in bodies {
-            // Determine the average forces on the body
-            let mut total_force_y = 0 as i64;
-            let mut total_force_x = 0 as i64;
-            let mut num_pixels = 0 as i64;
-            // Special Y axis tracking so we can hit ground
-            let mut max_y = 0;
+    pub fn diffuse_pressure(&mut self) {
+        let cells = &self.mat_map;
+        let width = self.map_width;
+        self.pressure
+            .step(|y, x| lets_gas_through(cells[y * width + x].contents));
+    }
+
+    pub fn swap_materials(&mut self, y1: usize, x1: usize, y2: usize, x2: usize) {
+        let a = self.index(y1, x1);
+        let b = self.index(y2, x2);
+        let (first, second) = (self.mat_map[a].contents, self.mat_map[b].contents);
+        self.set_contents(a, second);
+        self.set_contents(b, first);
+    }
+
+    pub fn remove_at_position(&mut self, y: usize, x: usize) {
+        if !self.in_bounds(y, x) {
+            return;
+        }
+        let index = self.index(y, x);
+        self.set_contents(index, None);
+    }
+
+    pub fn apply_forces<R: Rng>(&mut self, rng: &mut R) {
+        // Given the current forces on each object, sum them over the body then move every
+        // pixel by the result. This way we can get bodies to move together.
+        let mut moving = Vec::new();
+        // Terminal velocity along either axis: what something would reach falling the
+        // whole height of the map, so no drop is cut short.
+        let max_speed = (2.0 * GRAVITY * self.map_height as f32).sqrt();
+        let mut bodies = bodies::find_bodies(self, self.map_height, self.map_width);
+        if self.rupture(&bodies) {
+            // The pieces go their own way from now on.
+            bodies = bodies::find_bodies(self, self.map_height, self.map_width);
+        }
+        let mut owner = vec![usize::MAX; self.mat_map.len()];
+        for (number, body) in bodies.iter().enumerate() {
+            for &(y, x) in body {
+                owner[self.index(y, x)] = number;
+            }
+        }
+        for (number, body) in bodies.into_iter().enumerate() {
+            // Determine the total force, momentum and mass of the body. Forces change
+            // the velocity the body already has, and dividing by mass means heavy
+            // bodies need a bigger push.
+            let mut total_force_y = 0.0;
+            let mut total_force_x = 0.0;
+            let mut momentum_y = 0.0;
+            let mut momentum_x = 0.0;
+            let mut carried_y = 0.0;
+            let mut carried_x = 0.0;
+            let mut mass = 0.0;
             for coord in &body {
                 let contents = self.contents_at_index(coord.0, coord.1).unwrap();
-                total_force_y += contents.force_y as i64;
-                total_force_x += contents.force_x as i64;
-                num_pixels += 1;
-                max_y = std::cmp::max(max_y, coord.0);
+                let density = contents.mat.density();
+                total_force_y += contents.force_y;
+                total_force_x += contents.force_x;
+                momentum_y += contents.velocity_y * density;
+                momentum_x += contents.velocity_x * density;
+                carried_y += contents.offset_y * density;
+                carried_x += contents.offset_x * density;
+                mass += density;
+            }
+            let velocity_y = ((momentum_y + total_force_y) / mass).clamp(-max_speed, max_speed);
+            let mut velocity_x = ((momentum_x + total_force_x) / mass).clamp(-max_speed, max_speed);
+
+            // Solids and powders sitting on something, or on the bottom of the map, rub
+            // against it. Fluids slip.
+            let state = self.material_at_index(body[0].0, body[0].1).state();
+            let supported = body.iter().any(|&(y, x)| match self.offset(y, x, 1, 0) {
+                Some(index) => self.mat_map[index].contents.is_some() && owner[index] != number,
+                None => true,
+            });
+            if supported && matches!(state, State::Solid | State::Powder) {
+                let grip = FRICTION * GRAVITY;
+                velocity_x = if velocity_x.abs() <= grip {
+                    0.0
+                } else {
+                    velocity_x - grip * velocity_x.signum()
+                };
             }
 
-            // Override the forces
-            let mut avg_force_y = total_force_y / num_pixels;
-            let avg_force_x = total_force_x / num_pixels;
+            // Only whole cells can be moved, so whatever's left over is carried to the
+            // next tick. Positive velocity is up.
+            let travel_y = velocity_y + carried_y / mass;
+            let travel_x = velocity_x + carried_x / mass;
+            let step_y = -travel_y.trunc() as isize;
+            let step_x = travel_x.trunc() as isize;
+            let offset = (travel_y.fract(), travel_x.fract());
+            moving.push((body, step_y, step_x, velocity_y, velocity_x, offset));
+        }
+        // Move rising bodies top first and everything else bottom first, so whatever is
+        // following behind can take the cells they leave this tick.
+        moving.sort_by_key(|(body, step_y, ..)| {
+            if *step_y < 0 {
+                (0, body.iter().map(|c| c.0 as isize).min())
+            } else {
+                (1, body.iter().map(|c| -(c.0 as isize)).min())
+            }
+        });
+
+        // Bodies are lifted out of the map one at a time and dropped back in wherever
+        // they fit, so nothing can move into a cell that's still taken. It's moved out
+        // rather than copied, so don't read the map through self until it's put back.
+        let mut new_mat_map = std::mem::take(&mut self.mat_map);
+        for (y, row) in new_mat_map.chunks_mut(self.map_width).enumerate() {
+            if self.row_counts[y] == 0 {
+                continue;
+            }
+            // Forces only last the tick they were gathered in. What they did to the
+            // velocity carries on.
+            for contents in row.iter_mut().filter_map(|cell| cell.contents.as_mut()) {
+                contents.force_y = 0.0;
+                contents.force_x = 0.0;
+            }
+        }
+
+        for (body, step_y, step_x, velocity_y, velocity_x, offset) in moving {
+            let mut records = Vec::with_capacity(body.len());
             for coord in &body {
-                let mut contents = self.contents_at_index(coord.0, coord.1).unwrap();
-                contents.force_y = 0;
-                contents.force_x = 0;
-                if avg_force_y < 0 && max_y == self.map_height - 1 {
-                    avg_force_y = 0; // Prevent materials from falling below floor
+                let index = self.index(coord.0, coord.1);
+                records.push(new_mat_map[index].contents.take().unwrap());
+                self.row_counts[coord.0] -= 1;
+            }
+
+            // Try the full move first, then each axis on its own, so a body pushed
+            // sideways into a wall still falls. Each goes as far as it can.
+            let mut moves = vec![(step_y, step_x)];
+            if step_y != 0 && step_x != 0 {
+                moves.push((step_y, 0));
+                moves.push((0, step_x));
+            }
+            // The way loose material settles: powders and liquids fall, gases rise.
+            let def = records[0].mat.substance();
+            let settle = match def.state {
+                State::Powder | State::Liquid => 1,
+                State::Gas => -1,
+                State::Solid => 0,
+            };
+            // A loose grain pulled the way it settles but sitting on something, whether
+            // or not it's built up a whole cell of movement yet. Positive velocity is up.
+            let resting = body.len() == 1
+                && settle != 0
+                && -velocity_y * settle as f32 > 0.0
+                && self.sweep(&new_mat_map, &body, settle, 0).0 == (0, 0);
+            if resting {
+                // A grain that can't fall straight down slides off diagonally, but only
+                // where the slope beside it is steeper than its angle of repose. Fluids
+                // have no angle of repose, and once they can't go any further they run
+                // sideways to level out.
+                let mut sides = [-1, 1];
+                if rng.gen_bool(0.5) {
+                    sides.swap(0, 1);
+                }
+                let (drop, run) = match def.state {
+                    State::Powder => repose_step(def.angle_of_repose),
+                    _ => (1, 1),
+                };
+                for side in sides {
+                    if self.slope_exceeds(&new_mat_map, body[0], settle, side, drop, run) {
+                        // Down the slope if it can, otherwise across the ledge towards it.
+                        moves.push((settle, side));
+                        moves.push((0, side));
+                    }
                 }
-                let new_index = self.index_with_force(
-                    coord.0,
-                    coord.1,
-                    avg_force_y as i8,
-                    avg_force_x as i8,
-                );
-                if new_index > self.max_index {
-                    continue; // Item fell outside the map
+                if def.state != State::Powder {
+                    for side in sides {
+                        let reach = self.free_run(&new_mat_map, body[0], side, def.spread);
+                        if reach > 0 {
+                            moves.push((0, side * reach as isize));
+                        }
+                    }
                 }
-                new_mat_map[new_index].contents = Some(contents);
+            }
+
+            let (_, blocked_y, blocked_x) = self.sweep(&new_mat_map, &body, step_y, step_x);
+            let (dy, dx) = moves
+                .into_iter()
+                .map(|(dy, dx)| self.sweep(&new_mat_map, &body, dy, dx).0)
+                .find(|&reached| reached != (0, 0))
+                .unwrap_or((0, 0));
+            // Running into something stops the body along that axis.
+            let (velocity_y, offset_y) = if blocked_y {
+                (0.0, 0.0)
+            } else {
+                (velocity_y, offset.0)
+            };
+            let (velocity_x, offset_x) = if blocked_x {
+                (0.0, 0.0)
+            } else {
+                (velocity_x, offset.1)
+            };
+            for (coord, mut contents) in body.iter().zip(records) {
+                let index = self.offset(coord.0, coord.1, dy, dx).unwrap();
+                contents.velocity_y = velocity_y;
+                contents.velocity_x = velocity_x;
+                contents.offset_y = offset_y;
+                contents.offset_x = offset_x;
+                new_mat_map[index].contents = Some(contents);
+                self.row_counts[index / self.map_width] += 1;
             }
         }
 
         self.mat_map = new_mat_map;
     }
+
+    fn sweep(
+        &self,
+        cells: &[Cell],
+        body: &[(usize, usize)],
+        dy: isize,
+        dx: isize,
+    ) -> ((isize, isize), bool, bool) {
+        // Walk the body along (dy, dx) a cell at a time so it can't pass through
+        // anything, and say how far it got and whether it ran into something going up or
+        // down, sideways, or both.
+        let fits = |oy: isize, ox: isize| {
+            body.iter().all(|&(y, x)| {
+                self.offset(y, x, oy, ox)
+                    .is_some_and(|index| cells[index].contents.is_none())
+            })
+        };
+        let steps = dy.abs().max(dx.abs());
+        let mut reached = (0, 0);
+        for i in 1..=steps {
+            let next = (dy * i / steps, dx * i / steps);
+            if fits(next.0, next.1) {
+                reached = next;
+                continue;
+            }
+            let hit_y = next.0 != reached.0 && !fits(next.0, reached.1);
+            let hit_x = next.1 != reached.1 && !fits(reached.0, next.1);
+            // Neither axis blocked on its own means it caught a corner, which stops it
+            // both ways.
+            return (reached, hit_y || !hit_x, hit_x || !hit_y);
+        }
+        (reached, false, false)
+    }
+
+    fn rupture(&mut self, bodies: &[Vec<(usize, usize)>]) -> bool {
+        // A body bursts when the net force pushing its cells away from its middle is
+        // more than they can hold together. Only cells with a strength hold a body
+        // together, so a body without any never bursts.
+        let mut ruptured = false;
+        let mut next_fragment = None;
+        for body in bodies {
+            if body.len() < 2 {
+                continue;
+            }
+            let cells = body.len() as f32;
+            let centre_y = body.iter().map(|c| c.0 as f32).sum::<f32>() / cells;
+            let centre_x = body.iter().map(|c| c.1 as f32).sum::<f32>() / cells;
+            let mut outward = 0.0;
+            let mut strength = None;
+            for &(y, x) in body {
+                let contents = self.contents_at_index(y, x).unwrap();
+                if let Some(cell_strength) = contents.mat.strength() {
+                    strength = Some(strength.unwrap_or(0.0) + cell_strength);
+                }
+                let dy = y as f32 - centre_y;
+                let dx = x as f32 - centre_x;
+                let distance = (dy * dy + dx * dx).sqrt();
+                if distance > 0.0 {
+                    // Positive force_y is up, the other way to the grid.
+                    outward += (-contents.force_y * dy + contents.force_x * dx) / distance;
+                }
+            }
+            if !strength.is_some_and(|strength| outward > strength) {
+                continue;
+            }
+
+            // Fresh ids, past any fragment already on the map.
+            let first = *next_fragment.get_or_insert_with(|| {
+                let cells = self.mat_map.iter().filter_map(|cell| cell.contents);
+                cells.map(|contents| contents.fragment).max().unwrap_or(0) + 1
+            });
+            for &(y, x) in body {
+                let angle = (y as f32 - centre_y).atan2(x as f32 - centre_x);
+                let slice = (angle + std::f32::consts::PI) / std::f32::consts::TAU;
+                let piece = (slice * FRAGMENTS as f32) as u32 % FRAGMENTS;
+                if let Some(contents) = self.contents_mut(y, x) {
+                    contents.fragment = first + piece;
+                }
+            }
+            next_fragment = Some(first + FRAGMENTS);
+            ruptured = true;
+        }
+        ruptured
+    }
+
+    fn offset(&self, y: usize, x: usize, dy: isize, dx: isize) -> Option<usize> {
+        // Index of the cell (dy, dx) away, or None past the edge of the map.
+        let y = y.checked_add_signed(dy)?;
+        let x = x.checked_add_signed(dx)?;
+        if self.in_bounds(y, x) {
+            Some(self.index(y, x))
+        } else {
+            None
+        }
+    }
+
+    fn free_run(&self, cells: &[Cell], coord: (usize, usize), side: isize, limit: u16) -> u16 {
+        // How many empty cells in a row there are beside coord, up to limit.
+        (1..=limit)
+            .take_while(|&dx| {
+                self.offset(coord.0, coord.1, 0, side * dx as isize)
+                    .is_some_and(|index| cells[index].contents.is_none())
+            })
+            .count() as u16
+    }
+
+    fn slope_exceeds(
+        &self,
+        cells: &[Cell],
+        coord: (usize, usize),
+        settle: isize,
+        side: isize,
+        drop: usize,
+        run: usize,
+    ) -> bool {
+        // Whether the `drop` cells `run` columns beside coord, going the way it settles,
+        // are all empty.
+        (1..=drop as isize).all(|dy| {
+            self.offset(coord.0, coord.1, dy * settle, side * run as isize)
+                .is_some_and(|index| cells[index].contents.is_none())
+        })
+    }
+}
+
+fn repose_step(angle_of_repose: f32) -> (usize, usize) {
+    // How far down the ground beside a grain has to fall away, and how many columns
+    // across, before it slides: the rise and run of its angle of repose in whole cells.
+    // One down and one across is 45 degrees.
+    let slope = angle_of_repose.to_radians().tan();
+    (1..=MAX_RUN)
+        .map(|run| ((slope * run as f32).round().max(1.0) as usize, run))
+        .min_by(|a, b| {
+            let error = |(drop, run): (usize, usize)| (drop as f32 / run as f32 - slope).abs();
+            error(*a).total_cmp(&error(*b))
+        })
+        .unwrap()
+}
+
+fn lets_gas_through(contents: Option<MaterialRecord>) -> bool {
+    // Gas gets through empty cells, other gases and the gaps between loose grains, but
+    // not through solids or liquids. A burning cell is as tight as what's burning.
+    contents.is_none_or(|contents| matches!(contents.mat.state(), State::Gas | State::Powder))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::Material;
+    use crate::simulation_engine::SimulationEngine;
+
+    fn fragments_after_lighting(casing: Option<&str>) -> bool {
+        // A 16x15 block of explosive on the floor, lit in the middle, and whether
+        // anything on the map has broken into fragments within 200 ticks.
+        let mut engine = SimulationEngine::with_seed(60, 60, 3);
+        let explosive = Material::from_name("explosive").unwrap();
+        let map = engine.map_mut();
+        for x in 0..60 {
+            map.add_material(59, x, Material::from_name("mortar").unwrap());
+        }
+        for y in 44..59 {
+            for x in 22..38 {
+                let wall = y == 44 || y == 58 || x == 22 || x == 37;
+                match casing {
+                    Some(name) if wall => {
+                        map.add_material(y, x, Material::from_name(name).unwrap())
+                    }
+                    _ => map.add_material(y, x, explosive),
+                }
+            }
+        }
+        map.add_material(51, 30, Material::from_name("fire").unwrap());
+        (0..200).any(|_| {
+            engine.step();
+            engine
+                .map()
+                .mat_map
+                .iter()
+                .any(|cell| cell.contents.is_some_and(|contents| contents.fragment != 0))
+        })
+    }
+
+    #[test]
+    fn confined_charge_ruptures_its_casing() {
+        assert!(fragments_after_lighting(Some("cardboard")));
+    }
+
+    #[test]
+    fn open_charge_never_fragments() {
+        assert!(!fragments_after_lighting(None));
+    }
 }
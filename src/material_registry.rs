@@ -0,0 +1,262 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::material::RGB;
+
+// The materials shipped with the game, used unless another file is installed.
+const BUILTIN: &str = include_str!("../materials.toml");
+
+static REGISTRY: OnceLock<MaterialRegistry> = OnceLock::new();
+
+// Degrees Celsius. Where cells start unless their material says otherwise, and what
+// exposed cells cool towards.
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Solid,
+    Powder,
+    Liquid,
+    Gas,
+}
+
+impl State {
+    pub fn is_rigid(&self) -> bool {
+        // Only solids stick to their neighbours and move as one body.
+        *self == State::Solid
+    }
+}
+
+// Index of a material in the registry. Only meaningful for the installed registry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub u16);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDef {
+    pub name: String,
+    pub colour: [u8; 3],
+    pub density: f32,
+    pub state: State,
+    #[serde(default)]
+    pub flammability: f32,
+    #[serde(default)]
+    pub ignition_point: Option<f32>,
+    #[serde(default)]
+    pub burn_duration: i16,
+    #[serde(default)]
+    pub burn_rate: f32,
+    #[serde(default)]
+    pub pressure_yield: i8,
+    #[serde(default)]
+    pub flame_colour: Option<[u8; 3]>,
+    #[serde(default)]
+    pub residue: Option<String>,
+    #[serde(default)]
+    pub sparks: f32,
+    #[serde(default)]
+    pub spark_speed: f32,
+    #[serde(default)]
+    pub spark_lifetime: u16,
+    #[serde(default = "default_angle_of_repose")]
+    pub angle_of_repose: f32,
+    #[serde(default)]
+    pub spread: u16,
+    #[serde(default)]
+    pub extinguishes: bool,
+    #[serde(default)]
+    pub fixed: bool,
+    #[serde(default)]
+    pub strength: Option<f32>,
+    #[serde(default)]
+    pub lifetime: u16,
+    #[serde(default)]
+    pub buoyancy: f32,
+    #[serde(default)]
+    pub smoke: f32,
+    #[serde(default = "default_temperature")]
+    pub temperature: f32,
+    #[serde(default)]
+    pub conductivity: f32,
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+fn default_angle_of_repose() -> f32 {
+    45.0
+}
+
+fn default_temperature() -> f32 {
+    AMBIENT_TEMPERATURE
+}
+
+impl MaterialDef {
+    pub fn rgb(&self) -> RGB {
+        RGB {
+            red: self.colour[0] as usize,
+            green: self.colour[1] as usize,
+            blue: self.colour[2] as usize,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MaterialFile {
+    material: Vec<MaterialDef>,
+}
+
+pub struct MaterialRegistry {
+    defs: Vec<MaterialDef>,
+    fire: MaterialId,
+    pressure: MaterialId,
+    smoke: Option<MaterialId>,
+    // What each material leaves behind once it's burnt, in registry order.
+    residues: Vec<Option<MaterialId>>,
+}
+
+impl MaterialRegistry {
+    pub fn builtin() -> MaterialRegistry {
+        MaterialRegistry::parse(BUILTIN).expect("Built in materials.toml is invalid")
+    }
+
+    pub fn load(path: &Path) -> Result<MaterialRegistry, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        MaterialRegistry::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<MaterialRegistry, String> {
+        let file: MaterialFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        if file.material.len() > u16::MAX as usize {
+            return Err("too many materials".to_string());
+        }
+        for (i, def) in file.material.iter().enumerate() {
+            if def.name.is_empty() || def.name.contains(char::is_whitespace) {
+                return Err(format!("material name '{}' must be one word", def.name));
+            }
+            if file.material[..i]
+                .iter()
+                .any(|other| other.name == def.name)
+            {
+                return Err(format!("material '{}' is defined twice", def.name));
+            }
+            if !(0.0..=1.0).contains(&def.flammability) {
+                return Err(format!(
+                    "{}: flammability must be between 0 and 1",
+                    def.name
+                ));
+            }
+            if def.flammability > 0.0 && def.ignition_point.is_none() {
+                return Err(format!(
+                    "{}: flammable materials need an ignition_point",
+                    def.name
+                ));
+            }
+            if def.strength.is_some_and(|strength| strength < 0.0) {
+                return Err(format!("{}: strength can't be negative", def.name));
+            }
+            if def.burn_rate < 0.0 {
+                return Err(format!("{}: burn_rate can't be negative", def.name));
+            }
+            if !(0.0..=1.0).contains(&def.conductivity) {
+                return Err(format!(
+                    "{}: conductivity must be between 0 and 1",
+                    def.name
+                ));
+            }
+            if !(0.0..=1.0).contains(&def.sparks) {
+                return Err(format!("{}: sparks must be between 0 and 1", def.name));
+            }
+            if def.sparks > 0.0 && def.spark_lifetime == 0 {
+                return Err(format!("{}: sparks need a spark_lifetime", def.name));
+            }
+            if !(0.0..=1.0).contains(&def.buoyancy) {
+                return Err(format!("{}: buoyancy must be between 0 and 1", def.name));
+            }
+            if !(0.0..=1.0).contains(&def.smoke) {
+                return Err(format!("{}: smoke must be between 0 and 1", def.name));
+            }
+            if !(0.0..90.0).contains(&def.angle_of_repose) {
+                return Err(format!(
+                    "{}: angle_of_repose must be at least 0 and under 90 degrees",
+                    def.name
+                ));
+            }
+            if def.density < 0.0 {
+                return Err(format!("{}: density can't be negative", def.name));
+            }
+        }
+        let find = |name: &str| {
+            file.material
+                .iter()
+                .position(|def| def.name == name)
+                .map(|i| MaterialId(i as u16))
+        };
+        let mut residues = Vec::with_capacity(file.material.len());
+        for def in &file.material {
+            let residue =
+                match &def.residue {
+                    Some(name) => Some(find(name).ok_or_else(|| {
+                        format!("{}: residue '{}' isn't a material", def.name, name)
+                    })?),
+                    None => None,
+                };
+            residues.push(residue);
+        }
+        let require =
+            |name: &str| find(name).ok_or_else(|| format!("material '{}' is required", name));
+        Ok(MaterialRegistry {
+            fire: require("fire")?,
+            pressure: require("pressure")?,
+            smoke: find("smoke"),
+            residues,
+            defs: file.material,
+        })
+    }
+
+    pub fn get(&self, id: MaterialId) -> &MaterialDef {
+        &self.defs[id.0 as usize]
+    }
+
+    pub fn find(&self, name: &str) -> Option<MaterialId> {
+        self.defs
+            .iter()
+            .position(|def| def.name == name)
+            .map(|i| MaterialId(i as u16))
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = MaterialId> {
+        (0..self.defs.len() as u16).map(MaterialId)
+    }
+
+    pub fn fire(&self) -> MaterialId {
+        self.fire
+    }
+
+    pub fn pressure(&self) -> MaterialId {
+        self.pressure
+    }
+
+    pub fn smoke(&self) -> Option<MaterialId> {
+        self.smoke
+    }
+
+    pub fn residue(&self, id: MaterialId) -> Option<MaterialId> {
+        self.residues[id.0 as usize]
+    }
+}
+
+pub fn registry() -> &'static MaterialRegistry {
+    REGISTRY.get_or_init(MaterialRegistry::builtin)
+}
+
+pub fn install(registry: MaterialRegistry) -> Result<(), String> {
+    // Swap in a different set of materials. Only possible before anything has
+    // looked a material up, so call it first thing at startup.
+    REGISTRY
+        .set(registry)
+        .map_err(|_| "materials are already in use".to_string())
+}
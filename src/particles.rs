@@ -0,0 +1,135 @@
+use crate::air::Air;
+use crate::material::Material;
+use crate::material_map::MaterialMap;
+use crate::material_registry::State;
+use crate::simulation_engine::GRAVITY;
+
+// Fraction of its speed through the air a spark loses each tick, so it drifts with the
+// wind.
+const DRAG: f32 = 0.02;
+// Fraction of its speed a spark keeps when it bounces off something solid.
+const RESTITUTION: f32 = 0.4;
+
+// A spark. Unlike grid cells it can sit between cells and move any distance per tick.
+// Positions are in cells, velocities in cells per tick, and positive y is down like
+// the grid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Particle {
+    pub y: f32,
+    pub x: f32,
+    pub velocity_y: f32,
+    pub velocity_x: f32,
+    pub age: u16,
+    pub lifetime: u16,
+    pub colour: [u8; 3],
+}
+
+#[derive(Default)]
+pub struct Particles {
+    particles: Vec<Particle>,
+}
+
+impl Particles {
+    pub fn new() -> Particles {
+        Particles::default()
+    }
+
+    pub fn spawn(&mut self, particle: Particle) {
+        self.particles.push(particle);
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Particle> {
+        self.particles.iter()
+    }
+
+    pub fn step(&mut self, map: &MaterialMap, air: &Air) {
+        for particle in self.particles.iter_mut() {
+            particle.age += 1;
+            // The air's y is up, a spark's is down.
+            let (wind_y, wind_x) = air.velocity_at(particle.y, particle.x);
+            particle.velocity_y += GRAVITY;
+            particle.velocity_y += (-wind_y - particle.velocity_y) * DRAG;
+            particle.velocity_x += (wind_x - particle.velocity_x) * DRAG;
+
+            // Walk the move at most a cell at a time so fast sparks can't tunnel
+            // through thin walls.
+            let distance = particle.velocity_y.abs().max(particle.velocity_x.abs());
+            let steps = distance.ceil().max(1.0) as usize;
+            let step_y = particle.velocity_y / steps as f32;
+            let step_x = particle.velocity_x / steps as f32;
+            for _ in 0..steps {
+                let y = particle.y + step_y;
+                let x = particle.x + step_x;
+                if !blocks(map, y, x) {
+                    particle.y = y;
+                    particle.x = x;
+                    continue;
+                }
+                // Bounce off whichever face it ran into.
+                let hit_y = blocks(map, y, particle.x);
+                let hit_x = blocks(map, particle.y, x);
+                if hit_y || !hit_x {
+                    particle.velocity_y = -particle.velocity_y;
+                }
+                if hit_x || !hit_y {
+                    particle.velocity_x = -particle.velocity_x;
+                }
+                particle.velocity_y *= RESTITUTION;
+                particle.velocity_x *= RESTITUTION;
+                break;
+            }
+        }
+        // Sparks burn out, or leave the map and are gone for good.
+        self.particles.retain(|particle| {
+            particle.age < particle.lifetime
+                && particle.y >= 0.0
+                && particle.x >= 0.0
+                && map.in_bounds(particle.y as usize, particle.x as usize)
+        });
+    }
+
+    pub fn draw(&self, pixel_buffer: &mut [u8], width: usize, height: usize) {
+        // Drawn over the grid into an RGB24 buffer, fading out as they burn down.
+        for particle in &self.particles {
+            if particle.y < 0.0 || particle.x < 0.0 {
+                continue;
+            }
+            let (y, x) = (particle.y as usize, particle.x as usize);
+            if y >= height || x >= width {
+                continue;
+            }
+            let lifetime = particle.lifetime.max(1) as u32;
+            let left = (particle.lifetime.saturating_sub(particle.age) as u32).min(lifetime);
+            let offset = (y * width + x) * 3;
+            for (channel, value) in particle.colour.iter().enumerate() {
+                pixel_buffer[offset + channel] = (*value as u32 * left / lifetime) as u8;
+            }
+        }
+    }
+}
+
+fn blocks(map: &MaterialMap, y: f32, x: f32) -> bool {
+    // Sparks fly through flames and gas but bounce off everything else.
+    if y < 0.0 || x < 0.0 {
+        return false;
+    }
+    match map.contents_at_index(y as usize, x as usize) {
+        Some(contents) => match contents.mat {
+            Material::Compound(_) => contents.mat.def().state != State::Gas,
+            _ => false,
+        },
+        None => false,
+    }
+}
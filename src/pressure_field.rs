@@ -0,0 +1,180 @@
+// Share of the difference a cell trades with each open neighbour per tick. Has to stay
+// under a quarter or four neighbours can overshoot each other.
+const DIFFUSION: f32 = 0.2;
+// Fraction of its pressure a cell keeps each tick as the hot gas cools.
+const RETENTION: f32 = 0.99;
+// Pressure too weak to push anything is dropped, so a blast doesn't leave a faint haze
+// over the whole map forever.
+const MINIMUM: f32 = 0.01;
+
+use std::ops::Range;
+
+// Gas pressure on the same grid as the map. Burning releases it, it spreads out through
+// open cells but not through solids or liquids, and its slope pushes things around.
+pub struct PressureField {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+    // Rows and columns of the smallest box holding every cell with pressure, so the
+    // rest of the map can be skipped. None while there's no pressure anywhere.
+    extent: Option<(Range<usize>, Range<usize>)>,
+}
+
+impl PressureField {
+    pub fn new(width: usize, height: usize) -> PressureField {
+        PressureField {
+            width,
+            height,
+            values: vec![0.0; width * height],
+            extent: None,
+        }
+    }
+
+    pub fn get(&self, y: usize, x: usize) -> f32 {
+        // Outside the map is open air.
+        if y < self.height && x < self.width {
+            self.values[y * self.width + x]
+        } else {
+            0.0
+        }
+    }
+
+    pub fn set(&mut self, y: usize, x: usize, pressure: f32) {
+        if y < self.height && x < self.width {
+            self.values[y * self.width + x] = pressure;
+            self.include(y, x);
+        }
+    }
+
+    pub fn add(&mut self, y: usize, x: usize, pressure: f32) {
+        if y < self.height && x < self.width {
+            self.values[y * self.width + x] += pressure;
+            self.include(y, x);
+        }
+    }
+
+    fn include(&mut self, y: usize, x: usize) {
+        self.extent = Some(match self.extent.take() {
+            Some((rows, columns)) => (
+                rows.start.min(y)..rows.end.max(y + 1),
+                columns.start.min(x)..columns.end.max(x + 1),
+            ),
+            None => (y..y + 1, x..x + 1),
+        });
+    }
+
+    pub fn extent(&self) -> Option<(Range<usize>, Range<usize>)> {
+        // The cells with pressure plus a cell all round, which is everywhere the
+        // pressure can have a slope or spread to in one step.
+        let (rows, columns) = self.extent.clone()?;
+        Some((
+            rows.start.saturating_sub(1)..(rows.end + 1).min(self.height),
+            columns.start.saturating_sub(1)..(columns.end + 1).min(self.width),
+        ))
+    }
+
+    pub fn total(&self) -> f32 {
+        self.values.iter().sum()
+    }
+
+    pub fn step<F: Fn(usize, usize) -> bool>(&mut self, open: F) {
+        // One diffusion step. Pressure only moves between open cells, so walls hold it
+        // in, and the edge of the map vents it into the open air.
+        let (rows, columns) = match self.extent() {
+            Some(extent) => extent,
+            None => return,
+        };
+        self.displace(&open, &rows, &columns);
+        // Everything outside the box is zero, so only the box needs remembering.
+        let span = columns.len();
+        let mut before = Vec::with_capacity(rows.len() * span);
+        for y in rows.clone() {
+            before.extend_from_slice(&self.values[y * self.width + columns.start..][..span]);
+        }
+        let before_at = |y: usize, x: usize| {
+            if rows.contains(&y) && columns.contains(&x) {
+                before[(y - rows.start) * span + x - columns.start]
+            } else {
+                0.0
+            }
+        };
+        self.extent = None;
+        for y in rows.clone() {
+            for x in columns.clone() {
+                if !open(y, x) {
+                    if self.values[y * self.width + x] != 0.0 {
+                        self.include(y, x);
+                    }
+                    continue;
+                }
+                let here = before_at(y, x);
+                let mut pressure = here;
+                for (ny, nx) in neighbours(y, x) {
+                    if ny >= self.height || nx >= self.width {
+                        pressure -= here * DIFFUSION;
+                    } else if open(ny, nx) {
+                        pressure += (before_at(ny, nx) - here) * DIFFUSION;
+                    }
+                }
+                pressure *= RETENTION;
+                if pressure < MINIMUM {
+                    self.values[y * self.width + x] = 0.0;
+                } else {
+                    self.values[y * self.width + x] = pressure;
+                    self.include(y, x);
+                }
+            }
+        }
+    }
+
+    fn displace<F: Fn(usize, usize) -> bool>(
+        &mut self,
+        open: &F,
+        rows: &Range<usize>,
+        columns: &Range<usize>,
+    ) {
+        // Whatever moved into a cell holding pressure pushes it out into the open cells
+        // around it, so none is lost. If there's nowhere for it to go it waits there.
+        for y in rows.clone() {
+            for x in columns.clone() {
+                let index = y * self.width + x;
+                if self.values[index] == 0.0 || open(y, x) {
+                    continue;
+                }
+                let outlets: Vec<(usize, usize)> = neighbours(y, x)
+                    .into_iter()
+                    .filter(|&(ny, nx)| ny >= self.height || nx >= self.width || open(ny, nx))
+                    .collect();
+                if outlets.is_empty() {
+                    continue;
+                }
+                let share = self.values[index] / outlets.len() as f32;
+                self.values[index] = 0.0;
+                for (ny, nx) in outlets {
+                    // Past the edge of the map it's gone into the open air.
+                    self.add(ny, nx, share);
+                }
+            }
+        }
+    }
+
+    pub fn gradient(&self, y: usize, x: usize) -> (f32, f32) {
+        // How much harder it's pushed from below than above and from the left than the
+        // right. Positive y is up, like forces.
+        let below = self.get(y + 1, x);
+        let above = if y > 0 { self.get(y - 1, x) } else { 0.0 };
+        let left = if x > 0 { self.get(y, x - 1) } else { 0.0 };
+        let right = self.get(y, x + 1);
+        (below - above, left - right)
+    }
+}
+
+fn neighbours(y: usize, x: usize) -> [(usize, usize); 4] {
+    // Off the top or left edge wraps round to a huge index, which is past the edge too.
+    [
+        (y.wrapping_sub(1), x),
+        (y + 1, x),
+        (y, x.wrapping_sub(1)),
+        (y, x + 1),
+    ]
+}
@@ -0,0 +1,256 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::material::Material;
+use crate::scene;
+use crate::simulation_engine::SimulationEngine;
+
+// Replay files hold every input tagged with the tick it was applied on, then the
+// scene the recording started from:
+//
+//   FireworkEngineerReplay <version>
+//   stroke <true|false>
+//   <tick> <input> [<args>]
+//   ...
+//   scene
+//   <scene file>
+const MAGIC: &str = "FireworkEngineerReplay";
+pub const VERSION: u32 = 1;
+
+// Where the record and playback hotkeys read and write.
+pub const QUICKSAVE_PATH: &str = "firework.replay";
+
+// Everything a user can do to the simulation, independent of the front end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    SelectMaterial(Material),
+    BeginStroke { y: i32, x: i32 },
+    ContinueStroke { y: i32, x: i32 },
+    EndStroke,
+    TogglePause,
+    ToggleGenerator,
+}
+
+pub struct Recording {
+    // The scene at the tick recording started, with the RNG freshly seeded.
+    pub scene: Vec<u8>,
+    // Whether a brush stroke was already in progress when recording started.
+    pub stroke_active: bool,
+    pub inputs: Vec<(u64, Input)>,
+}
+
+impl Recording {
+    pub fn start(engine: &SimulationEngine) -> Recording {
+        let mut scene = Vec::new();
+        scene::write(engine, &mut scene).expect("Writing to memory can't fail");
+        Recording {
+            scene,
+            stroke_active: engine.stroke_active(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, tick: u64, input: Input) {
+        self.inputs.push((tick, input));
+    }
+
+    pub fn engine(&self) -> Result<SimulationEngine, String> {
+        // A fresh engine in the state the recording started from.
+        let mut engine = scene::read(&self.scene[..])?;
+        if self.stroke_active {
+            engine.resume_stroke();
+        }
+        Ok(engine)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        self.write(&mut writer)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Recording, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Recording::read(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "{} {}", MAGIC, VERSION)?;
+        writeln!(w, "stroke {}", self.stroke_active)?;
+        for (tick, input) in &self.inputs {
+            match *input {
+                Input::SelectMaterial(material) => {
+                    writeln!(w, "{} select {}", tick, scene::material_fields(material))?
+                }
+                Input::BeginStroke { y, x } => writeln!(w, "{} begin {} {}", tick, y, x)?,
+                Input::ContinueStroke { y, x } => writeln!(w, "{} continue {} {}", tick, y, x)?,
+                Input::EndStroke => writeln!(w, "{} end", tick)?,
+                Input::TogglePause => writeln!(w, "{} pause", tick)?,
+                Input::ToggleGenerator => writeln!(w, "{} generator", tick)?,
+            }
+        }
+        writeln!(w, "scene")?;
+        w.write_all(&self.scene)?;
+        w.flush()
+    }
+
+    pub fn read<R: BufRead>(mut r: R) -> Result<Recording, String> {
+        let mut recording = Recording {
+            scene: Vec::new(),
+            stroke_active: false,
+            inputs: Vec::new(),
+        };
+        let mut line = String::new();
+        let mut line_number = 0;
+        loop {
+            line.clear();
+            line_number += 1;
+            if r.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+                return Err("replay has no scene section".to_string());
+            }
+            let mut fields = line.split_whitespace();
+            let first = match fields.next() {
+                Some(first) => first,
+                None => continue,
+            };
+
+            if line_number == 1 {
+                if first != MAGIC {
+                    return Err("not a replay file".to_string());
+                }
+                let version: u32 = scene::parse(fields.next(), line_number, "version")?;
+                if version > VERSION {
+                    return Err(format!(
+                        "replay version {} is newer than supported version {}",
+                        version, VERSION
+                    ));
+                }
+                continue;
+            }
+
+            match first {
+                "scene" => break,
+                "stroke" => {
+                    recording.stroke_active = scene::parse(fields.next(), line_number, "stroke")?
+                }
+                _ => {
+                    let tick = scene::parse(Some(first), line_number, "tick")?;
+                    let name = fields
+                        .next()
+                        .ok_or_else(|| format!("line {}: missing input", line_number))?;
+                    let input = match name {
+                        "select" => {
+                            Input::SelectMaterial(scene::parse_material(&mut fields, line_number)?)
+                        }
+                        "begin" => Input::BeginStroke {
+                            y: scene::parse(fields.next(), line_number, "y")?,
+                            x: scene::parse(fields.next(), line_number, "x")?,
+                        },
+                        "continue" => Input::ContinueStroke {
+                            y: scene::parse(fields.next(), line_number, "y")?,
+                            x: scene::parse(fields.next(), line_number, "x")?,
+                        },
+                        "end" => Input::EndStroke,
+                        "pause" => Input::TogglePause,
+                        "generator" => Input::ToggleGenerator,
+                        _ => return Err(format!("line {}: unknown input '{}'", line_number, name)),
+                    };
+                    if let Some(&(last_tick, _)) = recording.inputs.last() {
+                        if tick < last_tick {
+                            return Err(format!("line {}: input out of order", line_number));
+                        }
+                    }
+                    recording.inputs.push((tick, input));
+                }
+            }
+        }
+        r.read_to_end(&mut recording.scene)
+            .map_err(|e| e.to_string())?;
+        Ok(recording)
+    }
+}
+
+// Feeds a recording's inputs back into an engine as its clock reaches them.
+pub struct Playback {
+    inputs: Vec<(u64, Input)>,
+    next: usize,
+}
+
+impl Playback {
+    pub fn new(recording: &Recording) -> Playback {
+        Playback {
+            inputs: recording.inputs.clone(),
+            next: 0,
+        }
+    }
+
+    pub fn due(&mut self, tick: u64) -> Vec<Input> {
+        let mut due = Vec::new();
+        while self.next < self.inputs.len() && self.inputs[self.next].0 <= tick {
+            due.push(self.inputs[self.next].1);
+            self.next += 1;
+        }
+        due
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next == self.inputs.len()
+    }
+}
+
+pub fn play(recording: &Recording, ticks: u64) -> Result<SimulationEngine, String> {
+    // Headless playback: run the recording for `ticks` ticks and return the engine.
+    let mut engine = recording.engine()?;
+    engine.start_playback(recording);
+    for _ in 0..ticks {
+        engine.step();
+    }
+    // Inputs made after the last tick ran but before recording stopped.
+    engine.play_due_inputs();
+    Ok(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene_of(engine: &SimulationEngine) -> Vec<u8> {
+        let mut scene = Vec::new();
+        scene::write(engine, &mut scene).unwrap();
+        scene
+    }
+
+    #[test]
+    fn saved_recording_plays_back_like_the_live_run() {
+        let mut live = SimulationEngine::with_seed(60, 60, 11);
+        for _ in 0..10 {
+            live.step();
+        }
+        live.start_recording();
+        live.apply_input(Input::SelectMaterial(Material::from_name("sand").unwrap()));
+        live.apply_input(Input::BeginStroke { y: 10, x: 30 });
+        for _ in 0..20 {
+            live.step();
+        }
+        live.apply_input(Input::ContinueStroke { y: 12, x: 34 });
+        live.apply_input(Input::EndStroke);
+        live.apply_input(Input::ToggleGenerator);
+        for _ in 0..40 {
+            live.step();
+        }
+        // Left for the very last tick, with no step after it.
+        live.apply_input(Input::SelectMaterial(Material::from_name("water").unwrap()));
+        live.apply_input(Input::BeginStroke { y: 40, x: 10 });
+        let recording = live.stop_recording().unwrap();
+
+        let mut saved = Vec::new();
+        recording.write(&mut saved).unwrap();
+        let recording = Recording::read(&saved[..]).unwrap();
+        let played = play(&recording, 60).unwrap();
+
+        assert_eq!(played.tick(), live.tick());
+        assert_eq!(scene_of(&played), scene_of(&live));
+    }
+}
@@ -0,0 +1,473 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::air::Wind;
+use crate::material::Material;
+use crate::material_registry::registry;
+use crate::particles::Particle;
+use crate::simulation_engine::SimulationEngine;
+
+// Scene files are plain text so they diff well and can be tweaked by hand:
+//
+//   FireworkEngineerScene <version>
+//   <setting> <value>          one per line, in any order
+//   cells
+//   <y> <x> <force_y> <force_x> <material> [<duration> <pressure>] [damp] [age <ticks>]
+//       [temperature <degrees>] [fuel <material>] [velocity <y> <x>]
+//       [offset <y> <x>] [front <cells>] [fragment <id>]
+//   spark <y> <x> <velocity_y> <velocity_x> <age> <lifetime> <RRGGBB>
+//   pressure <y> <x> <pressure>
+//   air <row> <column> <velocity_y> <velocity_x>
+//
+// Only occupied cells, cells with pressure and moving air are written; air is given
+// per air cell, not per map cell. Fire carries its duration and pressure, plus its fuel
+// when it isn't plain fire and how far it's run along a fuse. Temperature is left out
+// while it matches the material's starting temperature. Pressure cells with a strength
+// from before version 11 are added to the field.
+const MAGIC: &str = "FireworkEngineerScene";
+// 1: integer forces. 2: forces may be fractional. 3: cells may be damp.
+// 4: cells may have an age. 5: cells may have a temperature. 6: fire may have a fuel.
+// 7: sparks in flight. 8: cells may have a velocity and pressure a strength.
+// 9: fire may have a front. 10: cells may belong to a fragment.
+// 11: pressure is a field rather than cells. 12: moving air. 13: wind.
+// 14: cells may be part of the way to the next cell.
+pub const VERSION: u32 = 14;
+
+// Where the save and load hotkeys read and write.
+pub const QUICKSAVE_PATH: &str = "firework.scene";
+
+pub fn save(engine: &SimulationEngine, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    write(engine, &mut writer).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn load(path: &Path) -> Result<SimulationEngine, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    read(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn write<W: Write>(engine: &SimulationEngine, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "{} {}", MAGIC, VERSION)?;
+    writeln!(w, "width {}", engine.width())?;
+    writeln!(w, "height {}", engine.height())?;
+    writeln!(w, "seed {}", engine.seed())?;
+    writeln!(w, "tick {}", engine.tick())?;
+    writeln!(w, "updating {}", engine.is_updating())?;
+    writeln!(w, "generator {}", engine.is_generating())?;
+    let wind = engine.wind();
+    writeln!(w, "wind {} {}", wind.ground, wind.aloft)?;
+    writeln!(
+        w,
+        "selected {}",
+        material_fields(engine.selected_material())
+    )?;
+    writeln!(w, "cells")?;
+
+    let map = engine.map();
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            if let Some(record) = map.contents_at_index(y, x) {
+                write!(
+                    w,
+                    "{} {} {} {} {}",
+                    y,
+                    x,
+                    record.force_y,
+                    record.force_x,
+                    material_fields(record.mat)
+                )?;
+                if record.damp {
+                    write!(w, " damp")?;
+                }
+                if record.age > 0 {
+                    write!(w, " age {}", record.age)?;
+                }
+                if record.temperature != record.mat.def().temperature {
+                    write!(w, " temperature {}", record.temperature)?;
+                }
+                if let Material::Fire { fuel, front, .. } = record.mat {
+                    if fuel != record.mat.id() {
+                        write!(w, " fuel {}", registry().get(fuel).name)?;
+                    }
+                    if front != 0.0 {
+                        write!(w, " front {}", front)?;
+                    }
+                }
+                if record.fragment != 0 {
+                    write!(w, " fragment {}", record.fragment)?;
+                }
+                if record.velocity_y != 0.0 || record.velocity_x != 0.0 {
+                    write!(w, " velocity {} {}", record.velocity_y, record.velocity_x)?;
+                }
+                if record.offset_y != 0.0 || record.offset_x != 0.0 {
+                    write!(w, " offset {} {}", record.offset_y, record.offset_x)?;
+                }
+                writeln!(w)?;
+            }
+        }
+    }
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            let pressure = map.pressure().get(y, x);
+            if pressure != 0.0 {
+                writeln!(w, "pressure {} {} {}", y, x, pressure)?;
+            }
+        }
+    }
+    let air = engine.air();
+    for row in 0..air.rows() {
+        for column in 0..air.columns() {
+            let (velocity_y, velocity_x) = air.cell(row, column);
+            if velocity_y != 0.0 || velocity_x != 0.0 {
+                writeln!(w, "air {} {} {} {}", row, column, velocity_y, velocity_x)?;
+            }
+        }
+    }
+    for spark in engine.particles().iter() {
+        let [red, green, blue] = spark.colour;
+        writeln!(
+            w,
+            "spark {} {} {} {} {} {} {:02x}{:02x}{:02x}",
+            spark.y,
+            spark.x,
+            spark.velocity_y,
+            spark.velocity_x,
+            spark.age,
+            spark.lifetime,
+            red,
+            green,
+            blue
+        )?;
+    }
+    w.flush()
+}
+
+pub fn read<R: BufRead>(r: R) -> Result<SimulationEngine, String> {
+    let mut lines = r.lines().enumerate();
+
+    let header = match lines.next() {
+        Some((_, line)) => line.map_err(|e| e.to_string())?,
+        None => return Err("empty scene file".to_string()),
+    };
+    let mut header_fields = header.split_whitespace();
+    if header_fields.next() != Some(MAGIC) {
+        return Err("not a scene file".to_string());
+    }
+    let version: u32 = parse(header_fields.next(), 1, "version")?;
+    if version > VERSION {
+        return Err(format!(
+            "scene version {} is newer than supported version {}",
+            version, VERSION
+        ));
+    }
+
+    let mut width = None;
+    let mut height = None;
+    let mut seed = 0;
+    let mut tick = 0;
+    let mut updating = true;
+    let mut generator = false;
+    let mut selected = Material::default_material();
+    let mut wind = Wind::default();
+    let mut engine = None;
+
+    for (number, line) in lines {
+        let line_number = number + 1;
+        let line = line.map_err(|e| e.to_string())?;
+        let mut fields = line.split_whitespace();
+        let key = match fields.next() {
+            Some(key) => key,
+            None => continue,
+        };
+
+        let engine = match engine.as_mut() {
+            Some(engine) => engine,
+            None => {
+                match key {
+                    "width" => width = Some(parse(fields.next(), line_number, key)?),
+                    "height" => height = Some(parse(fields.next(), line_number, key)?),
+                    "seed" => seed = parse(fields.next(), line_number, key)?,
+                    "tick" => tick = parse(fields.next(), line_number, key)?,
+                    "updating" => updating = parse(fields.next(), line_number, key)?,
+                    "generator" => generator = parse(fields.next(), line_number, key)?,
+                    "selected" => selected = parse_material(&mut fields, line_number)?,
+                    "wind" => {
+                        wind = Wind {
+                            ground: parse(fields.next(), line_number, "wind")?,
+                            aloft: parse(fields.next(), line_number, "wind aloft")?,
+                        }
+                    }
+                    "cells" => {
+                        let (width, height) = match (width, height) {
+                            (Some(width), Some(height)) if width > 0 && height > 0 => {
+                                (width, height)
+                            }
+                            _ => return Err("scene has no valid width and height".to_string()),
+                        };
+                        let mut new_engine = SimulationEngine::with_seed(width, height, seed);
+                        new_engine.set_tick(tick);
+                        new_engine.set_updating(updating);
+                        new_engine.set_generator(generator);
+                        new_engine.select_material(selected);
+                        new_engine.set_wind(wind);
+                        engine = Some(new_engine);
+                    }
+                    // Settings from newer minor revisions are skipped rather than rejected.
+                    _ => {}
+                }
+                continue;
+            }
+        };
+
+        if key == "spark" {
+            let spark = parse_spark(&mut fields, line_number)?;
+            engine.particles_mut().spawn(spark);
+            continue;
+        }
+        if key == "pressure" {
+            let y = parse(fields.next(), line_number, "y")?;
+            let x = parse(fields.next(), line_number, "x")?;
+            let pressure = parse(fields.next(), line_number, "pressure")?;
+            engine.map_mut().pressure_mut().set(y, x, pressure);
+            continue;
+        }
+        if key == "air" {
+            let row = parse(fields.next(), line_number, "row")?;
+            let column = parse(fields.next(), line_number, "column")?;
+            let velocity_y = parse(fields.next(), line_number, "velocity_y")?;
+            let velocity_x = parse(fields.next(), line_number, "velocity_x")?;
+            engine
+                .air_mut()
+                .set_cell(row, column, velocity_y, velocity_x);
+            continue;
+        }
+
+        let y: usize = parse(Some(key), line_number, "y")?;
+        let x: usize = parse(fields.next(), line_number, "x")?;
+        let force_y = parse(fields.next(), line_number, "force_y")?;
+        let force_x = parse(fields.next(), line_number, "force_x")?;
+        let mut material = parse_material(&mut fields, line_number)?;
+        if !engine.map().in_bounds(y, x) {
+            return Err(format!(
+                "line {}: cell ({}, {}) is outside the map",
+                line_number, y, x
+            ));
+        }
+        let mut damp = false;
+        let mut age = 0;
+        let mut temperature = material.def().temperature;
+        let mut velocity = (0.0, 0.0);
+        let mut offset = (0.0, 0.0);
+        let mut fragment = 0;
+        while let Some(field) = fields.next() {
+            match field {
+                "damp" => damp = true,
+                "age" => age = parse(fields.next(), line_number, "age")?,
+                "temperature" => temperature = parse(fields.next(), line_number, "temperature")?,
+                "strength" => match material {
+                    Material::Pressure { ref mut strength } => {
+                        *strength = parse(fields.next(), line_number, "strength")?
+                    }
+                    _ => {
+                        return Err(format!(
+                            "line {}: only pressure has a strength",
+                            line_number
+                        ))
+                    }
+                },
+                "front" => match material {
+                    Material::Fire { ref mut front, .. } => {
+                        *front = parse(fields.next(), line_number, "front")?
+                    }
+                    _ => return Err(format!("line {}: only fire has a front", line_number)),
+                },
+                "fragment" => fragment = parse(fields.next(), line_number, "fragment")?,
+                "velocity" => {
+                    velocity = (
+                        parse(fields.next(), line_number, "velocity_y")?,
+                        parse(fields.next(), line_number, "velocity_x")?,
+                    )
+                }
+                "offset" => {
+                    offset = (
+                        parse(fields.next(), line_number, "offset_y")?,
+                        parse(fields.next(), line_number, "offset_x")?,
+                    )
+                }
+                "fuel" => {
+                    let name = fields.next().unwrap_or_default();
+                    let id = registry()
+                        .find(name)
+                        .ok_or_else(|| format!("line {}: unknown fuel '{}'", line_number, name))?;
+                    match material {
+                        Material::Fire { ref mut fuel, .. } => *fuel = id,
+                        _ => return Err(format!("line {}: only fire has a fuel", line_number)),
+                    }
+                }
+                other => return Err(format!("line {}: unexpected '{}'", line_number, other)),
+            }
+        }
+        let map = engine.map_mut();
+        map.add_material(y, x, material);
+        if let Some(record) = map.contents_mut(y, x) {
+            record.force_y = force_y;
+            record.force_x = force_x;
+            record.damp = damp;
+            record.age = age;
+            record.temperature = temperature;
+            record.velocity_y = velocity.0;
+            record.velocity_x = velocity.1;
+            record.offset_y = offset.0;
+            record.offset_x = offset.1;
+            record.fragment = fragment;
+        }
+    }
+
+    engine.ok_or_else(|| "scene has no cells section".to_string())
+}
+
+pub(crate) fn material_fields(material: Material) -> String {
+    match material {
+        Material::Fire {
+            duration, pressure, ..
+        } => {
+            format!("{} {} {}", material.name(), duration, pressure)
+        }
+        _ => material.name().to_string(),
+    }
+}
+
+pub(crate) fn parse_material<'a, I: Iterator<Item = &'a str>>(
+    fields: &mut I,
+    line_number: usize,
+) -> Result<Material, String> {
+    let name = fields
+        .next()
+        .ok_or_else(|| format!("line {}: missing material", line_number))?;
+    let mut material = Material::from_name(name)
+        .ok_or_else(|| format!("line {}: unknown material '{}'", line_number, name))?;
+    if let Material::Fire {
+        ref mut duration,
+        ref mut pressure,
+        ..
+    } = material
+    {
+        *duration = parse(fields.next(), line_number, "duration")?;
+        *pressure = parse(fields.next(), line_number, "pressure")?;
+    }
+    Ok(material)
+}
+
+fn parse_spark<'a, I: Iterator<Item = &'a str>>(
+    fields: &mut I,
+    line_number: usize,
+) -> Result<Particle, String> {
+    let y = parse(fields.next(), line_number, "y")?;
+    let x = parse(fields.next(), line_number, "x")?;
+    let velocity_y = parse(fields.next(), line_number, "velocity_y")?;
+    let velocity_x = parse(fields.next(), line_number, "velocity_x")?;
+    let age = parse(fields.next(), line_number, "age")?;
+    let lifetime = parse(fields.next(), line_number, "lifetime")?;
+    let colour = parse_hex_colour(fields.next(), line_number)?;
+    Ok(Particle {
+        y,
+        x,
+        velocity_y,
+        velocity_x,
+        age,
+        lifetime,
+        colour,
+    })
+}
+
+pub(crate) fn parse_hex_colour(field: Option<&str>, line_number: usize) -> Result<[u8; 3], String> {
+    // RRGGBB, as written for sparks and in palettes.
+    let hex = field.unwrap_or_default();
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("line {}: invalid colour '{}'", line_number, hex))?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+pub(crate) fn parse<T: std::str::FromStr>(
+    field: Option<&str>,
+    line_number: usize,
+    what: &str,
+) -> Result<T, String> {
+    let field = field.ok_or_else(|| format!("line {}: missing {}", line_number, what))?;
+    field
+        .parse()
+        .map_err(|_| format!("line {}: invalid {} '{}'", line_number, what, field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(engine: &SimulationEngine) -> String {
+        let mut scene = Vec::new();
+        write(engine, &mut scene).unwrap();
+        String::from_utf8(scene).unwrap()
+    }
+
+    #[test]
+    fn scene_survives_a_round_trip() {
+        // Burning wood and a lit fuse, left to run for a while so cells are moving,
+        // then with pressure, air, wind and a spark added by hand in case they've died
+        // down.
+        let mut engine = SimulationEngine::with_seed(40, 40, 3);
+        let wood = registry().find("wood").unwrap();
+        let fuse = registry().find("fuse").unwrap();
+        let map = engine.map_mut();
+        for x in 0..40 {
+            map.add_material(39, x, Material::from_name("mortar").unwrap());
+        }
+        for x in 10..20 {
+            map.add_material(38, x, Material::from_name("fuse").unwrap());
+            map.add_material(20, x, Material::from_name("sand").unwrap());
+        }
+        map.add_material(37, 15, Material::ignite(wood));
+        for _ in 0..5 {
+            engine.step();
+        }
+
+        let mut lit_fuse = Material::ignite(fuse);
+        if let Material::Fire { ref mut front, .. } = lit_fuse {
+            *front = 0.5;
+        }
+        let map = engine.map_mut();
+        map.add_material(38, 25, lit_fuse);
+        map.pressure_mut().add(30, 30, 2.5);
+        engine.air_mut().set_cell(1, 2, 0.25, -0.125);
+        engine.set_wind(Wind {
+            ground: 0.5,
+            aloft: 1.5,
+        });
+        engine.particles_mut().spawn(Particle {
+            y: 10.5,
+            x: 12.25,
+            velocity_y: -0.75,
+            velocity_x: 0.5,
+            age: 3,
+            lifetime: 40,
+            colour: [255, 128, 0],
+        });
+
+        let scene = written(&engine);
+        for key in [
+            " fuel ",
+            " front ",
+            "\npressure ",
+            "\nair ",
+            "\nwind ",
+            "\nspark ",
+        ] {
+            assert!(scene.contains(key), "scene has no{}", key.trim_end());
+        }
+        let read_back = read(scene.as_bytes()).unwrap();
+        assert_eq!(written(&read_back), scene);
+    }
+}
@@ -1,247 +1,936 @@
+#[cfg(feature = "gui")]
 use sdl2;
+#[cfg(feature = "gui")]
 use sdl2::event::Event;
+#[cfg(feature = "gui")]
+use std::path::Path;
 
-use time;
-use time::Duration;
+use std::time::Duration;
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::air::{Air, Wind};
 use crate::brushes;
 use crate::counter::Counter;
+use crate::export::FrameExporter;
+#[cfg(feature = "gui")]
+use crate::export::{self, ExportFormat};
+#[cfg(feature = "gui")]
+use crate::image_io::{self, Palette};
 use crate::material::Material;
 use crate::material_map::MaterialMap;
-use crate::window;
+use crate::material_registry::{registry, State, AMBIENT_TEMPERATURE};
+use crate::particles::{Particle, Particles};
+#[cfg(feature = "gui")]
+use crate::replay;
+use crate::replay::{Input, Playback, Recording};
+#[cfg(feature = "gui")]
+use crate::scene;
+
+// Length of one simulation tick in wall time.
+pub const TICK_DURATION: Duration = Duration::from_millis(10);
+// Upper bound on ticks run by a single advance(), so a stalled frame can't
+// snowball into an ever growing backlog.
+const MAX_TICKS_PER_ADVANCE: u32 = 10;
+// Cells per tick squared that everything falls by, cells and sparks alike.
+pub const GRAVITY: f32 = 0.03;
+// Ticks between two puffs of the sand generator.
+const GENERATOR_INTERVAL: u64 = 2;
+// How much of its flammability a damp composition keeps.
+const DAMP_FLAMMABILITY: f32 = 0.05;
+// Fraction of the difference to ambient a cell loses per tick for each face open to the air.
+const AIR_COOLING: f32 = 0.01;
+// Force on a cell per unit of pressure difference across it.
+const PRESSURE_FORCE: f32 = 8.0;
+// Wind raised per tick by a unit of pressure difference across an open cell.
+const AIR_FROM_PRESSURE: f32 = 0.3;
+// Upward wind raised per tick by each degree an open cell is above ambient.
+const HOT_AIR_LIFT: f32 = 0.001;
+// Force on a cell per cell per tick of difference between its velocity and the wind's.
+const AIR_DRAG: f32 = 0.005;
+// Wind too slow to move a gas at all, in cells per tick.
+const WIND_NOTICED: f32 = 0.01;
 
 pub struct SimulationEngine {
     buffer_width: usize,
     buffer_height: usize,
-    time_at_last_update: time::Instant,
-    time_at_last_render: time::Instant,
     generation_counter: Counter,
+    // Number of ticks simulated so far; the only clock the simulation reads.
+    tick: u64,
+    // Wall time handed to advance() that hasn't been turned into ticks yet.
+    accumulator: Duration,
     map: Box<MaterialMap>,
-    mouse_button_down: bool,
+    // Sparks thrown off burning cells, drawn over the map.
+    particles: Particles,
+    // Wind stirred up by blasts and hot gas.
+    air: Air,
+    stroke_active: bool,
     selected_material: Material,
-    pixel_buffer: [u8; window::SCREEN_WIDTH * window::SCREEN_HEIGHT * 3],
-    // Consider moving this into a different struct
-    elapsed: Duration,
-    frame_counter: i32,
-    update_counter: i32,
+    pixel_buffer: Vec<u8>,
     updating: bool,
     generator: bool,
+    // Every stochastic rule draws from this, so a seed plus a scene replays exactly.
+    seed: u64,
+    rng: StdRng,
+    recording: Option<Recording>,
+    playback: Option<Playback>,
+    exporter: Option<FrameExporter>,
 }
 
 impl SimulationEngine {
     pub fn new(width: usize, height: usize) -> SimulationEngine {
+        SimulationEngine::with_seed(width, height, rand::random())
+    }
+
+    pub fn with_seed(width: usize, height: usize, seed: u64) -> SimulationEngine {
         SimulationEngine {
             buffer_width: width,
             buffer_height: height,
-            time_at_last_update: time::Instant::now(),
-            time_at_last_render: time::Instant::now(),
-            generation_counter: Counter::new(),
-            mouse_button_down: false,
-            selected_material: Material::Sand,
+            generation_counter: Counter::new(0),
+            tick: 0,
+            accumulator: Duration::ZERO,
+            stroke_active: false,
+            selected_material: Material::default_material(),
             map: Box::new(MaterialMap::new(width, height)),
-            pixel_buffer: [0; window::SCREEN_HEIGHT * window::SCREEN_WIDTH * 3],
-            elapsed: Duration::seconds(0),
-            frame_counter: 0,
-            update_counter: 0,
+            particles: Particles::new(),
+            air: Air::new(width, height),
+            pixel_buffer: vec![0; width * height * 3],
             updating: true,
             generator: false,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            recording: None,
+            playback: None,
+            exporter: None,
         }
     }
 
-    pub fn handle_event(&mut self, event: &Event) {
-        match *event {
-            Event::KeyUp { keycode, .. } => match keycode.unwrap() {
-                // https://docs.rs/sdl2/latest/sdl2/keyboard/enum.Keycode.html
-                sdl2::keyboard::Keycode::S => {
-                    self.selected_material = Material::Sand;
-                }
-                sdl2::keyboard::Keycode::E => {
-                    self.selected_material = Material::Explosive;
-                }
-                sdl2::keyboard::Keycode::F => {
-                    self.selected_material = Material::Fire {
-                        duration: 30,
-                        pressure: 0,
-                    };
-                }
-                sdl2::keyboard::Keycode::P => {
-                    self.selected_material = Material::Pressure;
-                }
-                sdl2::keyboard::Keycode::W => {
-                    self.selected_material = Material::Wood;
-                }
-                sdl2::keyboard::Keycode::C => {
-                    self.selected_material = Material::Cardboard;
-                }
-                sdl2::keyboard::Keycode::Period => {
-                    self.generator = !self.generator;
-                }
-                sdl2::keyboard::Keycode::Space => {
-                    self.updating = !self.updating;
-                }
-                _ => {}
-            },
-            Event::MouseButtonDown { x, y, .. } => {
-                self.mouse_button_down = true;
-                println!("(Y, X) ({}, {})", y, x);
-                for cord in
-                    brushes::circle(5.0, y, x, self.buffer_height, self.buffer_width, 0.00001)
-                {
-                    self.add_selected_to_map(cord.0 as usize, cord.1 as usize);
-                }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn width(&self) -> usize {
+        self.buffer_width
+    }
+
+    pub fn height(&self) -> usize {
+        self.buffer_height
+    }
+
+    pub fn map(&self) -> &MaterialMap {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut MaterialMap {
+        &mut self.map
+    }
+
+    pub fn particles(&self) -> &Particles {
+        &self.particles
+    }
+
+    pub fn particles_mut(&mut self) -> &mut Particles {
+        &mut self.particles
+    }
+
+    pub fn air(&self) -> &Air {
+        &self.air
+    }
+
+    pub fn air_mut(&mut self) -> &mut Air {
+        &mut self.air
+    }
+
+    pub fn wind(&self) -> Wind {
+        self.air.wind()
+    }
+
+    pub fn set_wind(&mut self, wind: Wind) {
+        self.air.set_wind(wind);
+    }
+
+    pub fn replace_map(&mut self, map: MaterialMap) {
+        self.buffer_width = map.width();
+        self.buffer_height = map.height();
+        self.pixel_buffer = vec![0; map.width() * map.height() * 3];
+        let wind = self.air.wind();
+        self.air = Air::new(map.width(), map.height());
+        self.air.set_wind(wind);
+        *self.map = map;
+        self.particles.clear();
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub(crate) fn set_tick(&mut self, tick: u64) {
+        // Timers are measured relative to the clock, so they restart with it.
+        self.tick = tick;
+        self.generation_counter.reset(tick);
+    }
+
+    pub fn is_updating(&self) -> bool {
+        self.updating
+    }
+
+    pub fn set_updating(&mut self, updating: bool) {
+        self.updating = updating;
+    }
+
+    pub fn is_generating(&self) -> bool {
+        self.generator
+    }
+
+    pub fn set_generator(&mut self, generator: bool) {
+        self.generator = generator;
+    }
+
+    pub fn selected_material(&self) -> Material {
+        self.selected_material
+    }
+
+    pub fn select_material(&mut self, material: Material) {
+        self.selected_material = material;
+    }
+
+    pub fn stroke_active(&self) -> bool {
+        self.stroke_active
+    }
+
+    pub(crate) fn resume_stroke(&mut self) {
+        self.stroke_active = true;
+    }
+
+    pub fn apply_input(&mut self, input: Input) {
+        // The one way edits reach the simulation, so they can be recorded.
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(self.tick, input);
+        }
+        self.dispatch_input(input);
+    }
+
+    fn dispatch_input(&mut self, input: Input) {
+        match input {
+            Input::SelectMaterial(material) => self.select_material(material),
+            Input::BeginStroke { y, x } => {
+                self.stroke_active = true;
+                self.paint(y, x);
             }
-            Event::MouseButtonUp { .. } => self.mouse_button_down = false,
-            Event::MouseMotion { x, y, .. } => {
-                if self.mouse_button_down {
-                    for cord in
-                        brushes::circle(5.0, y, x, self.buffer_height, self.buffer_width, 0.00001)
-                    {
-                        self.add_selected_to_map(cord.0 as usize, cord.1 as usize);
-                    }
+            Input::ContinueStroke { y, x } => {
+                if self.stroke_active {
+                    self.paint(y, x);
                 }
             }
-            _ => {}
+            Input::EndStroke => self.stroke_active = false,
+            Input::TogglePause => self.updating = !self.updating,
+            Input::ToggleGenerator => self.generator = !self.generator,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn start_recording(&mut self) {
+        // Restart the RNG and timers the same way loading the saved scene will,
+        // so playback sees exactly what happened here.
+        self.reseed(self.seed);
+        self.set_tick(self.tick);
+        self.recording = Some(Recording::start(self));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
+    pub fn start_playback(&mut self, recording: &Recording) {
+        // Queue a recording's inputs. The engine should be in the recording's
+        // starting state, see Recording::engine().
+        self.playback = Some(Playback::new(recording));
+    }
+
+    pub fn is_playing_back(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    pub fn start_export(&mut self, exporter: FrameExporter) {
+        // The current state becomes the first frame, then one every `every` ticks.
+        self.exporter = Some(exporter);
+        self.export_frame();
+    }
+
+    pub fn stop_export(&mut self) -> Option<Result<u64, String>> {
+        self.exporter.take().map(|exporter| exporter.finish())
+    }
+
+    pub fn is_exporting(&self) -> bool {
+        self.exporter.is_some()
+    }
+
+    fn export_frame(&mut self) {
+        if self.exporter.is_none() {
+            return;
+        }
+        self.render();
+        if let Some(exporter) = self.exporter.as_mut() {
+            if let Err(e) = exporter.write_frame(self.tick, &self.pixel_buffer) {
+                println!("Export stopped: {}", e);
+                self.exporter = None;
+            }
+        }
+    }
+
+    fn paint(&mut self, y: i32, x: i32) {
+        for cord in brushes::circle(
+            &mut self.rng,
+            5.0,
+            y,
+            x,
+            self.buffer_height,
+            self.buffer_width,
+            0.00001,
+        ) {
+            self.add_selected_to_map(cord.0, cord.1);
         }
     }
 
     fn add_selected_to_map(&mut self, y: usize, x: usize) {
-        let mat = self.selected_material.clone();
+        let mat = self.selected_material;
         self.map.add_material(y, x, mat);
     }
 
-    pub fn update(&mut self, texture: &mut sdl2::render::Texture) {
-        let previous_update = self.time_at_last_update;
-        let time_elapsed = time::Instant::now() - previous_update;
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        // Turn wall time into whole ticks. Leftover time carries over to the next call.
+        if !self.updating {
+            return 0;
+        }
+        self.accumulator += elapsed;
+        let mut ticks = 0;
+        while self.accumulator >= TICK_DURATION {
+            if ticks == MAX_TICKS_PER_ADVANCE {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= TICK_DURATION;
+            self.step();
+            ticks += 1;
+        }
+        ticks
+    }
 
-        if self.updating {
-            if time_elapsed >= time::Duration::milliseconds(10) {
-                self.update_cell_positions(&time_elapsed);
-                self.time_at_last_update = time::Instant::now();
-                self.update_counter = self.update_counter + 1;
+    pub(crate) fn play_due_inputs(&mut self) {
+        // Apply whatever a recording being played back did on this tick.
+        if let Some(playback) = self.playback.as_mut() {
+            for input in playback.due(self.tick) {
+                self.dispatch_input(input);
             }
+            if self
+                .playback
+                .as_ref()
+                .is_some_and(|playback| playback.finished())
+            {
+                self.playback = None;
+            }
+        }
+    }
 
-            if self.generator && self.generation_counter.elapsed_gt(20) {
-                for cord in
-                    brushes::circle(10.0, 10, 600, self.buffer_height, self.buffer_width, 0.9)
-                {
-                    self.map.add_material(cord.0 as usize, cord.1 as usize, Material::Sand);
-                }
+    pub fn step(&mut self) {
+        // Advance the simulation by exactly one tick, regardless of wall time.
+        self.play_due_inputs();
+
+        self.update_cell_positions();
 
-                self.generation_counter.reset();
+        if self.generator
+            && self
+                .generation_counter
+                .elapsed_gt(self.tick, GENERATOR_INTERVAL)
+        {
+            // Sprinkle the default material in from the top, three quarters of the way across.
+            let x = (self.buffer_width * 3 / 4) as i32;
+            for cord in brushes::circle(
+                &mut self.rng,
+                10.0,
+                10,
+                x,
+                self.buffer_height,
+                self.buffer_width,
+                0.9,
+            ) {
+                self.map
+                    .add_material(cord.0, cord.1, Material::default_material());
             }
-        }
 
-        self.update_texture(texture);
+            self.generation_counter.reset(self.tick);
+        }
 
-        self.frame_counter = self.frame_counter + 1;
-        let last_render_time = self.time_at_last_render;
-        self.time_at_last_render = time::Instant::now();
-        let time_between_render = self.time_at_last_render - last_render_time;
-        self.elapsed = self.elapsed + time_between_render;
+        self.tick += 1;
 
-        if self.elapsed > time::Duration::seconds(1) {
-            println!(
-                "FPS {} - Updates/Second {}",
-                self.frame_counter, self.update_counter
-            );
-            self.frame_counter = 0;
-            self.update_counter = 0;
-            self.elapsed = Duration::seconds(0);
+        if self
+            .exporter
+            .as_ref()
+            .is_some_and(|exporter| exporter.wants(self.tick))
+        {
+            self.export_frame();
         }
     }
 
-    fn update_texture(&mut self, texture: &mut sdl2::render::Texture) {
-        self.pixel_buffer = [0; window::SCREEN_HEIGHT * window::SCREEN_WIDTH * 3];
+    pub fn render(&mut self) {
+        self.pixel_buffer.fill(0);
         for y in 0..self.buffer_height {
             for x in 0..self.buffer_width {
                 if let Some(cell) = self.map.contents_at_index(y, x) {
-                    let offset = y * window::SCREEN_WIDTH * 3 + x * 3;
-                    let rgb = cell.mat.rgb();
-                    self.pixel_buffer[offset + 0] = rgb.red as u8;
+                    let offset = (y * self.buffer_width + x) * 3;
+                    let mut rgb = cell.mat.rgb();
+                    if cell.damp {
+                        // Wet compositions look darker.
+                        rgb.red = rgb.red * 7 / 10;
+                        rgb.green = rgb.green * 7 / 10;
+                        rgb.blue = rgb.blue * 7 / 10;
+                    }
+                    let lifetime = cell.mat.def().lifetime as usize;
+                    if lifetime > 0 {
+                        // Fade out towards the end of its life.
+                        let left = lifetime.saturating_sub(cell.age as usize);
+                        rgb.red = rgb.red * left / lifetime;
+                        rgb.green = rgb.green * left / lifetime;
+                        rgb.blue = rgb.blue * left / lifetime;
+                    }
+                    self.pixel_buffer[offset] = rgb.red as u8;
                     self.pixel_buffer[offset + 1] = rgb.green as u8;
                     self.pixel_buffer[offset + 2] = rgb.blue as u8;
                 }
             }
         }
-        texture.update(None, &self.pixel_buffer, 2400).unwrap();
+        self.particles.draw(
+            &mut self.pixel_buffer,
+            self.buffer_width,
+            self.buffer_height,
+        );
+    }
+
+    pub fn pixel_buffer(&self) -> &[u8] {
+        // RGB24, one row after another. Call render() first to refresh it.
+        &self.pixel_buffer
+    }
+}
+
+#[cfg(feature = "gui")]
+impl SimulationEngine {
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => match keycode {
+                // https://docs.rs/sdl2/latest/sdl2/keyboard/enum.Keycode.html
+                sdl2::keyboard::Keycode::Period => {
+                    self.apply_input(Input::ToggleGenerator);
+                }
+                sdl2::keyboard::Keycode::Space => {
+                    self.apply_input(Input::TogglePause);
+                }
+                sdl2::keyboard::Keycode::R => match self.stop_recording() {
+                    Some(recording) => {
+                        let path = Path::new(replay::QUICKSAVE_PATH);
+                        match recording.save(path) {
+                            Ok(()) => println!("Saved recording to {}", path.display()),
+                            Err(e) => println!("Saving recording failed: {}", e),
+                        }
+                    }
+                    None => {
+                        self.start_recording();
+                        println!("Recording from tick {}", self.tick);
+                    }
+                },
+                sdl2::keyboard::Keycode::V => match self.stop_export() {
+                    Some(Ok(frames)) => {
+                        println!("Exported {} frames to {}", frames, export::QUICKSAVE_PATH)
+                    }
+                    Some(Err(e)) => println!("Export failed: {}", e),
+                    None => {
+                        let path = Path::new(export::QUICKSAVE_PATH);
+                        match FrameExporter::create(
+                            path,
+                            ExportFormat::from_path(path),
+                            self.width(),
+                            self.height(),
+                            2,
+                        ) {
+                            Ok(exporter) => {
+                                self.start_export(exporter);
+                                println!("Exporting frames to {}", path.display());
+                            }
+                            Err(e) => println!("Export failed: {}", e),
+                        }
+                    }
+                },
+                sdl2::keyboard::Keycode::T => {
+                    let path = Path::new(replay::QUICKSAVE_PATH);
+                    match Recording::load(path).and_then(|recording| {
+                        let engine = recording.engine()?;
+                        Ok((recording, engine))
+                    }) {
+                        Ok((_, engine))
+                            if engine.width() != self.width()
+                                || engine.height() != self.height() =>
+                        {
+                            println!(
+                                "Playback failed: recording is {}x{} but the window is {}x{}",
+                                engine.width(),
+                                engine.height(),
+                                self.width(),
+                                self.height()
+                            )
+                        }
+                        Ok((recording, engine)) => {
+                            *self = engine;
+                            self.start_playback(&recording);
+                            println!("Playing back {}", path.display());
+                        }
+                        Err(e) => println!("Playback failed: {}", e),
+                    }
+                }
+                sdl2::keyboard::Keycode::F5 => {
+                    let path = Path::new(scene::QUICKSAVE_PATH);
+                    match scene::save(self, path) {
+                        Ok(()) => println!("Saved {}", path.display()),
+                        Err(e) => println!("Save failed: {}", e),
+                    }
+                }
+                sdl2::keyboard::Keycode::F9 => {
+                    let path = Path::new(scene::QUICKSAVE_PATH);
+                    match scene::load(path) {
+                        Ok(engine)
+                            if engine.width() == self.width()
+                                && engine.height() == self.height() =>
+                        {
+                            *self = engine;
+                            println!("Loaded {}", path.display());
+                        }
+                        Ok(engine) => println!(
+                            "Load failed: scene is {}x{} but the window is {}x{}",
+                            engine.width(),
+                            engine.height(),
+                            self.width(),
+                            self.height()
+                        ),
+                        Err(e) => println!("Load failed: {}", e),
+                    }
+                }
+                sdl2::keyboard::Keycode::F6 => {
+                    let path = Path::new(image_io::QUICKSAVE_PATH);
+                    match image_io::export_png(&self.map, path) {
+                        Ok(()) => println!("Exported {}", path.display()),
+                        Err(e) => println!("Export failed: {}", e),
+                    }
+                }
+                sdl2::keyboard::Keycode::F10 => {
+                    let path = Path::new(image_io::QUICKSAVE_PATH);
+                    match image_io::import_png(path, &Palette::default()) {
+                        Ok((map, _))
+                            if map.width() != self.width() || map.height() != self.height() =>
+                        {
+                            println!(
+                                "Import failed: image is {}x{} but the window is {}x{}",
+                                map.width(),
+                                map.height(),
+                                self.width(),
+                                self.height()
+                            )
+                        }
+                        Ok((map, unknown)) => {
+                            for pixel in unknown {
+                                println!(
+                                    "Unknown colour {:?} at (Y, X) ({}, {})",
+                                    pixel.rgb, pixel.y, pixel.x
+                                );
+                            }
+                            self.replace_map(map);
+                            println!("Imported {}", path.display());
+                        }
+                        Err(e) => println!("Import failed: {}", e),
+                    }
+                }
+                // Material hotkeys come from the registry.
+                _ => {
+                    if let Some(material) = Material::from_key(&keycode.name()) {
+                        self.apply_input(Input::SelectMaterial(material));
+                    }
+                }
+            },
+            Event::MouseButtonDown { x, y, .. } => {
+                println!("(Y, X) ({}, {})", y, x);
+                self.apply_input(Input::BeginStroke { y, x });
+            }
+            Event::MouseButtonUp { .. } => self.apply_input(Input::EndStroke),
+            // Motion without a held button never paints, so it isn't worth recording.
+            Event::MouseMotion { x, y, .. } if self.stroke_active => {
+                self.apply_input(Input::ContinueStroke { y, x })
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update_texture(&mut self, texture: &mut sdl2::render::Texture) {
+        self.render();
+        texture
+            .update(None, &self.pixel_buffer, self.buffer_width * 3)
+            .unwrap();
     }
 }
 
 pub trait UpdateCellPositions {
-    fn update_cell_positions(&mut self, _elapsed: &time::Duration);
+    fn update_cell_positions(&mut self);
     fn gravity(&mut self);
+    fn gases(&mut self);
+    fn wetting(&mut self);
+    fn heat(&mut self);
+    fn fuses(&mut self);
+    fn density_layering(&mut self);
     fn fire(&mut self);
     fn pressure(&mut self);
+    fn air(&mut self);
+    fn sparks(&mut self);
 }
 
 impl UpdateCellPositions for SimulationEngine {
-    fn update_cell_positions(&mut self, _elapsed: &time::Duration) {
+    fn update_cell_positions(&mut self) {
         self.gravity();
+        self.gases();
+        self.wetting();
+        self.heat();
+        self.fuses();
         self.fire();
         self.pressure();
-        self.map.apply_forces();
+        self.air();
+        self.density_layering();
+        self.map.apply_forces(&mut self.rng);
+        self.sparks();
     }
 
     fn gravity(&mut self) {
+        // Weight is proportional to density, so everything falls at the same rate
+        // once apply_forces divides by mass.
+        for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
+            for x in 0..self.buffer_width {
+                if let Some(contents) = self.map.contents_at_index(y, x) {
+                    if contents.mat.state() == State::Gas {
+                        continue; // Gases rise instead, see gases()
+                    }
+                    let weight = contents.mat.density() * GRAVITY;
+                    self.map.add_force_at_index(y, x, -weight, 0.0);
+                }
+            }
+        }
+    }
+
+    fn gases(&mut self) {
+        // Buoyant gas drifts upwards and wanders sideways, and anything with a
+        // lifetime fades away once it's used it up.
         for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
             for x in 0..self.buffer_width {
-                self.map.add_force_at_index(y, x, -1, 0);
+                let contents = match self.map.contents_at_index(y, x) {
+                    Some(contents) => contents,
+                    None => continue,
+                };
+                let def = contents.mat.def();
+                if def.lifetime > 0 {
+                    if contents.age + 1 >= def.lifetime {
+                        self.map.remove_at_position(y, x);
+                        continue;
+                    }
+                    if let Some(record) = self.map.contents_mut(y, x) {
+                        record.age += 1;
+                    }
+                }
+                if contents.mat.state() != State::Gas || contents.mat.density() <= 0.0 {
+                    continue;
+                }
+                // Gas has too little momentum to carry any; it only goes where it's pushed.
+                if let Some(record) = self.map.contents_mut(y, x) {
+                    record.velocity_y = 0.0;
+                    record.velocity_x = 0.0;
+                }
+                let mass = contents.mat.density();
+                if def.buoyancy > 0.0 && self.rng.gen_bool(def.buoyancy as f64) {
+                    self.map.add_force_at_index(y, x, mass, 0.0);
+                } else {
+                    let side = if self.rng.gen_bool(0.5) { -mass } else { mass };
+                    self.map.add_force_at_index(y, x, 0.0, side);
+                }
             }
         }
     }
 
-    fn fire(&mut self) {
+    fn wetting(&mut self) {
+        // Liquids that extinguish put out fire they touch and soak anything flammable.
         for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
             for x in 0..self.buffer_width {
-                if let Some(mat) = self.map.contents_at_index(y, x) {
-                    match mat.mat {
-                        Material::Fire { duration, pressure } => {
-                            // Deteriorate the fire
-                            if duration <= 0 && pressure > 0 {
-                                self.map.add_material(
-                                    y,
-                                    x,
-                                    Material::Pressure,
-                                );
-                            } else if duration > 0 {
-                                self.map.add_material(
-                                    y,
-                                    x,
-                                    Material::Fire {
-                                        duration: duration - 1,
-                                        pressure,
-                                    },
-                                );
-                            } else {
-                                self.map.remove_at_position(y, x);
+                let liquid = match self.map.contents_at_index(y, x) {
+                    Some(contents) if contents.mat.def().extinguishes => contents,
+                    _ => continue,
+                };
+                for yi in (std::cmp::max(1, y) - 1)..(y + 2) {
+                    for xi in (std::cmp::max(1, x) - 1)..(x + 2) {
+                        let contents = match self.map.contents_at_index(yi, xi) {
+                            Some(contents) => contents,
+                            None => continue,
+                        };
+                        match contents.mat {
+                            // Something burning is left soaked and cooled to the
+                            // liquid's temperature; a bare flame just goes out.
+                            Material::Fire { fuel, .. } if fuel != registry().fire() => {
+                                if let Some(record) = self.map.contents_mut(yi, xi) {
+                                    record.mat = Material::Compound(fuel);
+                                    record.damp = true;
+                                    record.temperature = liquid.temperature;
+                                }
+                            }
+                            Material::Fire { .. } => self.map.remove_at_position(yi, xi),
+                            Material::Compound(_) if contents.mat.def().flammability > 0.0 => {
+                                if let Some(record) = self.map.contents_mut(yi, xi) {
+                                    record.damp = true;
+                                }
                             }
+                            _ => {}
                         }
-                        _ => {
+                    }
+                }
+            }
+        }
+    }
+
+    fn density_layering(&mut self) {
+        // Heavier loose material sinks through lighter loose material below it by
+        // trading places. The bigger the difference, the more likely the swap.
+        for y in (0..self.buffer_height.saturating_sub(1)).rev() {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
+            for x in 0..self.buffer_width {
+                let above = match self.map.contents_at_index(y, x) {
+                    Some(contents) => contents.mat,
+                    None => continue,
+                };
+                let below = match self.map.contents_at_index(y + 1, x) {
+                    Some(contents) => contents.mat,
+                    None => continue,
+                };
+                if above.state() == State::Solid || below.state() == State::Solid {
+                    continue;
+                }
+                let heavy = above.density();
+                let light = below.density();
+                if heavy > light && self.rng.gen_bool(((heavy - light) / heavy) as f64) {
+                    self.map.swap_materials(y, x, y + 1, x);
+                }
+            }
+        }
+    }
+
+    fn heat(&mut self) {
+        // Heat flows between touching cells at the pace of the poorer conductor, and
+        // leaks out of faces open to the air. Burning cells are kept hot.
+        let conductivity_of = |material: Material| match material {
+            // A burning fuse is still inside its coating.
+            Material::Fire { fuel, .. } if registry().get(fuel).burn_rate > 0.0 => {
+                registry().get(fuel).conductivity
+            }
+            _ => material.def().conductivity,
+        };
+        // Only the temperatures and conductivities from before anything changed are
+        // needed, not whole cells.
+        let width = self.buffer_width;
+        let mut before = vec![None; self.map.mat_map.len()];
+        for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
+            let row = y * width..(y + 1) * width;
+            for (record, cell) in before[row.clone()].iter_mut().zip(&self.map.mat_map[row]) {
+                *record = cell
+                    .contents
+                    .as_ref()
+                    .map(|contents| (contents.temperature, conductivity_of(contents.mat)));
+            }
+        }
+        let record_at = |y: usize, x: usize| before[y * width + x];
+        for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
+            for x in 0..self.buffer_width {
+                let (before_temperature, conductivity) = match record_at(y, x) {
+                    Some(record) => record,
+                    None => continue,
+                };
+                let mut temperature = before_temperature;
+                let neighbours = [
+                    (y.wrapping_sub(1), x),
+                    (y + 1, x),
+                    (y, x.wrapping_sub(1)),
+                    (y, x + 1),
+                ];
+                for (yi, xi) in neighbours {
+                    if !self.map.in_bounds(yi, xi) {
+                        continue;
+                    }
+                    match record_at(yi, xi) {
+                        Some((other_temperature, other_conductivity)) => {
+                            // A quarter each keeps four neighbours from overshooting.
+                            let rate = conductivity.min(other_conductivity) / 4.0;
+                            temperature += (other_temperature - before_temperature) * rate;
+                        }
+                        None => {
+                            let difference = AMBIENT_TEMPERATURE - before_temperature;
+                            temperature += difference * AIR_COOLING;
+                        }
+                    }
+                }
+                if let Some(record) = self.map.contents_mut(y, x) {
+                    if let Material::Fire { .. } = record.mat {
+                        temperature = temperature.max(record.mat.def().temperature);
+                    }
+                    record.temperature = temperature;
+                }
+            }
+        }
+    }
+
+    fn fuses(&mut self) {
+        // Fire runs along a fuse at its burn rate whatever the heat, and only lights
+        // what's around the far end once it gets there.
+        let mut fronts = Vec::new();
+        for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
+            for x in 0..self.buffer_width {
+                let contents = match self.map.contents_at_index(y, x) {
+                    Some(contents) => contents,
+                    None => continue,
+                };
+                match contents.mat {
+                    Material::Fire {
+                        duration,
+                        pressure,
+                        fuel,
+                        front,
+                    } => {
+                        let burn_rate = registry().get(fuel).burn_rate;
+                        if burn_rate <= 0.0 {
                             continue;
                         }
+                        let front = front + burn_rate;
+                        let burning = Material::Fire {
+                            duration,
+                            pressure,
+                            fuel,
+                            front,
+                        };
+                        self.map.replace_material(y, x, burning);
+                        // The front reached the next cell this tick.
+                        if front >= 1.0 && front - burn_rate < 1.0 {
+                            fronts.push((y, x, fuel, front));
+                        }
                     }
-                } else {
-                    continue;
+                    Material::Compound(id) if contents.mat.def().burn_rate > 0.0 => {
+                        // The coating keeps heat out, but any other flame touching the
+                        // fuse lights it.
+                        let mut lit = false;
+                        for yi in (std::cmp::max(1, y) - 1)..(y + 2) {
+                            for xi in (std::cmp::max(1, x) - 1)..(x + 2) {
+                                lit |= matches!(
+                                    self.map.contents_at_index(yi, xi).map(|c| c.mat),
+                                    Some(Material::Fire { fuel, .. }) if fuel != id
+                                );
+                            }
+                        }
+                        if lit {
+                            if let Some(record) = self.map.contents_mut(y, x) {
+                                record.mat = Material::ignite(id);
+                                record.damp = false;
+                            }
+                        }
+                    }
+                    _ => {}
                 }
-                // Catch everything else on fire
-                for yi in (std::cmp::max(1, y) - 1)..(y + 2) {
-                    for xi in (std::cmp::max(1, x) - 1)..(x + 2) {
-                        if yi == y && xi == x {
-                            continue; // Skip the pressure instance
+            }
+        }
+
+        // Fast fuses can run several cells in one tick, so keep passing the front on
+        // until it's used up.
+        while let Some((y, x, fuel, front)) = fronts.pop() {
+            let mut passed_on = false;
+            for yi in (std::cmp::max(1, y) - 1)..(y + 2) {
+                for xi in (std::cmp::max(1, x) - 1)..(x + 2) {
+                    if yi == y && xi == x {
+                        continue;
+                    }
+                    let contents = match self.map.contents_at_index(yi, xi) {
+                        Some(contents) => contents,
+                        None => continue,
+                    };
+                    match contents.mat {
+                        Material::Compound(id) if id == fuel => {
+                            passed_on = true;
+                            let def = registry().get(fuel);
+                            let burning = Material::Fire {
+                                duration: def.burn_duration,
+                                pressure: def.pressure_yield,
+                                fuel,
+                                front: front - 1.0,
+                            };
+                            if let Some(record) = self.map.contents_mut(yi, xi) {
+                                record.mat = burning;
+                                record.damp = false;
+                            }
+                            if front - 1.0 >= 1.0 {
+                                fronts.push((yi, xi, fuel, front - 1.0));
+                            }
                         }
-                        if let Some(contents) = self.map.contents_at_index(yi, xi) {
-                            match contents.mat {
-                                Material::Explosive => {
-                                    self.map.add_material(
-                                        yi,
-                                        xi,
-                                        Material::Fire {
-                                            duration: 15,
-                                            pressure: 20,
-                                        },
-                                    );
+                        // In a fuse more than a cell thick, the cell further along may
+                        // already have been lit from beside it. Its front is behind this
+                        // one's, so this still isn't the end.
+                        Material::Fire {
+                            fuel: other,
+                            front: ahead,
+                            ..
+                        } if other == fuel && ahead < front => passed_on = true,
+                        _ => {}
+                    }
+                }
+            }
+            if passed_on {
+                continue;
+            }
+            // Nowhere left for the front to go, so this is the end of the fuse. Light
+            // whatever it leads into.
+            for yi in (std::cmp::max(1, y) - 1)..(y + 2) {
+                for xi in (std::cmp::max(1, x) - 1)..(x + 2) {
+                    if let Some(contents) = self.map.contents_at_index(yi, xi) {
+                        if let Material::Compound(id) = contents.mat {
+                            if contents.mat.def().flammability > 0.0 {
+                                if let Some(record) = self.map.contents_mut(yi, xi) {
+                                    record.mat = Material::ignite(id);
+                                    record.damp = false;
                                 }
-                                _ => {}
                             }
                         }
                     }
@@ -250,50 +939,356 @@ impl UpdateCellPositions for SimulationEngine {
         }
     }
 
+    fn fire(&mut self) {
+        for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
+            for x in 0..self.buffer_width {
+                let contents = match self.map.contents_at_index(y, x) {
+                    Some(contents) => contents,
+                    None => continue,
+                };
+                match contents.mat {
+                    Material::Fire {
+                        duration,
+                        pressure,
+                        fuel,
+                        front,
+                    } => {
+                        // Smoke billows off the top of the flame
+                        let def = registry().get(fuel);
+                        if let Some(smoke) = registry().smoke() {
+                            let chance = def.smoke;
+                            if y > 0
+                                && !self.map.something_at_index(y - 1, x)
+                                && chance > 0.0
+                                && self.rng.gen_bool(chance as f64)
+                            {
+                                self.map.add_material(y - 1, x, Material::from_id(smoke));
+                            }
+                        }
+                        // Some fuels throw sparks in every direction
+                        if def.sparks > 0.0 && self.rng.gen_bool(def.sparks as f64) {
+                            let angle = self.rng.gen_range(0.0..std::f32::consts::TAU);
+                            let rgb = contents.mat.rgb();
+                            self.particles.spawn(Particle {
+                                y: y as f32 + 0.5,
+                                x: x as f32 + 0.5,
+                                velocity_y: angle.sin() * def.spark_speed,
+                                velocity_x: angle.cos() * def.spark_speed,
+                                age: 0,
+                                lifetime: def.spark_lifetime,
+                                colour: [rgb.red as u8, rgb.green as u8, rgb.blue as u8],
+                            });
+                        }
+                        // A fuse stays alight until its front has moved on, however
+                        // slowly it burns.
+                        if duration <= 0 && def.burn_rate > 0.0 && front < 1.0 {
+                            continue;
+                        }
+                        // Deteriorate the fire
+                        if duration <= 0 && pressure > 0 {
+                            // The gas it gives off goes into the pressure field
+                            self.map.pressure_mut().add(y, x, pressure as f32);
+                            self.map.remove_at_position(y, x);
+                        } else if duration > 0 {
+                            self.map.replace_material(
+                                y,
+                                x,
+                                Material::Fire {
+                                    duration: duration - 1,
+                                    pressure,
+                                    fuel,
+                                    front,
+                                },
+                            );
+                        } else if let Some(residue) = registry().residue(fuel) {
+                            // Burnt out, leaving char or ash still hot from the fire
+                            self.map.replace_material(y, x, Material::from_id(residue));
+                        } else {
+                            self.map.remove_at_position(y, x);
+                        }
+                    }
+                    Material::Compound(id) => {
+                        // Catch on fire once hot enough
+                        let def = contents.mat.def();
+                        let ignition_point = match def.ignition_point {
+                            Some(ignition_point) => ignition_point,
+                            None => continue,
+                        };
+                        let mut flammability = def.flammability;
+                        if contents.damp {
+                            flammability *= DAMP_FLAMMABILITY;
+                        }
+                        if contents.temperature >= ignition_point
+                            && flammability > 0.0
+                            && self.rng.gen_bool(flammability as f64)
+                        {
+                            // Keeps flying the way it was going, but it's dried out
+                            if let Some(record) = self.map.contents_mut(y, x) {
+                                record.mat = Material::ignite(id);
+                                record.damp = false;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
     fn pressure(&mut self) {
-        // For each Pressure instance on the grid, apply outward forces
-        let power: usize = 20;
-        let min_touched_pct: f64 = 0.4;
+        // Let the pressure spread out, then push everything down its slope. Pressure
+        // that can't get out pushes on the walls holding it in, so a confined blast hits
+        // much harder than one in the open.
+        self.map.diffuse_pressure();
+        let (rows, columns) = match self.map.pressure().extent() {
+            Some(extent) => extent,
+            None => return,
+        };
+        for y in rows {
+            for x in columns.clone() {
+                if !self.map.something_at_index(y, x) {
+                    continue;
+                }
+                let (force_y, force_x) = self.map.pressure().gradient(y, x);
+                self.map.add_force_at_index(
+                    y,
+                    x,
+                    force_y * PRESSURE_FORCE,
+                    force_x * PRESSURE_FORCE,
+                );
+            }
+        }
+    }
+
+    fn air(&mut self) {
+        // Blasts blow the air outwards and hot gas draws it upwards; then the air
+        // carries gases along and drags on everything else. Only cells with pressure
+        // around them or hot ones push the air, so the rest of the map is skipped.
+        if let Some((rows, columns)) = self.map.pressure().extent() {
+            for y in rows {
+                for x in columns.clone() {
+                    if !self.map.is_open(y, x) {
+                        continue;
+                    }
+                    let (push_y, push_x) = self.map.pressure().gradient(y, x);
+                    if push_y != 0.0 || push_x != 0.0 {
+                        self.air
+                            .push(y, x, push_y * AIR_FROM_PRESSURE, push_x * AIR_FROM_PRESSURE);
+                    }
+                }
+            }
+        }
         for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
             for x in 0..self.buffer_width {
-                if let Some(mat) = self.map.contents_at_index(y, x) {
-                    if mat.mat != Material::Pressure {
-                        continue; // If not a Pressure tile, we don't care
+                let cell = &self.map.mat_map[y * self.buffer_width + x];
+                if let Some(contents) = &cell.contents {
+                    if contents.temperature > AMBIENT_TEMPERATURE && self.map.is_open(y, x) {
+                        let lift = (contents.temperature - AMBIENT_TEMPERATURE) * HOT_AIR_LIFT;
+                        self.air.push(y, x, lift, 0.0);
                     }
-                } else {
+                }
+            }
+        }
+        self.air.step();
+
+        for y in 0..self.buffer_height {
+            if self.map.row_is_empty(y) {
+                continue;
+            }
+            for x in 0..self.buffer_width {
+                let contents = match self.map.contents_at_index(y, x) {
+                    Some(contents) => contents,
+                    None => continue,
+                };
+                let def = contents.mat.substance();
+                if def.fixed {
                     continue;
                 }
-                let mut num_touched: i64 = 0;
-                let mut num_possible: i64 = 0;
-                for yi in (std::cmp::max(power, y) - power)..(y + power) {
-                    for xi in (std::cmp::max(power, x) - power)..(x + power) {
-                        if yi == y && xi == x {
-                            continue; // Skip the pressure instance
+                let (wind_y, wind_x) = self.air.velocity_at(y as f32 + 0.5, x as f32 + 0.5);
+                if def.state == State::Gas {
+                    // Gas only moves whole cells, so it's blown along a cell at a time,
+                    // as often as the wind would take it that far.
+                    let mass = contents.mat.density();
+                    for (wind, vertical) in [(wind_y, true), (wind_x, false)] {
+                        let speed = wind.abs().min(1.0);
+                        if speed < WIND_NOTICED || !self.rng.gen_bool(speed as f64) {
+                            continue;
                         }
-                        num_possible += 1;
-
-                        // TODO: Maybe add higher force for closer objects
-                        let force_y = if yi < y { 1 } else { -1 };
-                        let force_x = if xi < x { -1 } else { 1 };
-
-                        // Push objects outwards if they're within a certain distance
-                        let distance = ((x as f64 - xi as f64).powf(2.0)
-                            + (y as f64 - yi as f64).powf(2.0))
-                        .sqrt();
-                        if distance < power as f64 {
-                            if let Some(mat) = self.map.contents_at_index(yi, xi) {
-                                if mat.mat != Material::Pressure {
-                                    self.map.add_force_at_index(yi, xi, force_y, force_x);
-                                    num_touched += 1;
-                                }
-                            }
+                        let force = mass * wind.signum();
+                        if vertical {
+                            self.map.add_force_at_index(y, x, force, 0.0);
+                        } else {
+                            self.map.add_force_at_index(y, x, 0.0, force);
                         }
                     }
+                } else {
+                    // Drag is the same for everything its size, so the lower its
+                    // density the more a breeze blows it about.
+                    self.map.add_force_at_index(
+                        y,
+                        x,
+                        (wind_y - contents.velocity_y) * AIR_DRAG,
+                        (wind_x - contents.velocity_x) * AIR_DRAG,
+                    );
                 }
-                if num_touched as f64 / (num_possible as f64) < min_touched_pct {
-                    self.map.remove_at_position(y, x);
-                }
             }
         }
     }
+
+    fn sparks(&mut self) {
+        // Sparks fly freely and only bounce off the map, so they move after it has.
+        self.particles.step(&self.map, &self.air);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded_run(seed: u64) -> Vec<u8> {
+        // Sand pouring in from the generator onto stars that have been set alight.
+        let mut engine = SimulationEngine::with_seed(60, 60, seed);
+        engine.apply_input(Input::ToggleGenerator);
+        engine.apply_input(Input::SelectMaterial(
+            Material::from_name("strontium_red").unwrap(),
+        ));
+        engine.apply_input(Input::BeginStroke { y: 50, x: 20 });
+        engine.apply_input(Input::SelectMaterial(Material::from_name("fire").unwrap()));
+        engine.apply_input(Input::ContinueStroke { y: 45, x: 20 });
+        engine.apply_input(Input::EndStroke);
+        for _ in 0..150 {
+            engine.step();
+        }
+        let mut scene = Vec::new();
+        crate::scene::write(&engine, &mut scene).unwrap();
+        scene
+    }
+
+    #[test]
+    fn same_seed_same_scene() {
+        assert_eq!(seeded_run(7), seeded_run(7));
+        assert_ne!(seeded_run(7), seeded_run(8));
+    }
+
+    fn lay_fuse(engine: &mut SimulationEngine, rows: std::ops::Range<usize>, length: usize) {
+        // Along the floor from x = 5, lit from its left end.
+        let map = engine.map_mut();
+        let floor = map.height() - 1;
+        for x in 0..map.width() {
+            map.add_material(floor, x, Material::from_name("mortar").unwrap());
+        }
+        for y in rows {
+            for x in 5..5 + length {
+                map.add_material(y, x, Material::from_name("fuse").unwrap());
+            }
+        }
+        map.add_material(floor - 1, 4, Material::from_name("fire").unwrap());
+    }
+
+    fn burning(engine: &SimulationEngine, name: &str) -> Vec<(usize, usize)> {
+        let id = registry().find(name).unwrap();
+        let map = engine.map();
+        (0..map.height())
+            .flat_map(|y| (0..map.width()).map(move |x| (y, x)))
+            .filter(|&(y, x)| {
+                matches!(
+                    map.contents_at_index(y, x).map(|c| c.mat),
+                    Some(Material::Fire { fuel, .. }) if fuel == id
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn thick_fuse_only_lights_its_far_end() {
+        // Explosive on top of a fuse three cells thick, halfway along, and more at the
+        // far end.
+        let mut engine = SimulationEngine::with_seed(50, 20, 3);
+        lay_fuse(&mut engine, 16..19, 40);
+        let explosive = Material::from_name("explosive").unwrap();
+        engine.map_mut().add_material(15, 25, explosive);
+        engine.map_mut().add_material(18, 45, explosive);
+
+        let mut lit = Vec::new();
+        for _ in 0..400 {
+            engine.step();
+            lit.extend(burning(&engine, "explosive"));
+        }
+        assert!(!lit.is_empty(), "the end of the fuse never lit anything");
+        assert!(
+            lit.iter().all(|&(_, x)| x >= 40),
+            "lit explosive beside the fuse"
+        );
+    }
+
+    #[test]
+    fn fuse_burns_at_its_burn_rate() {
+        let mut engine = SimulationEngine::with_seed(50, 20, 3);
+        lay_fuse(&mut engine, 18..19, 40);
+        let rate = registry().get(registry().find("fuse").unwrap()).burn_rate;
+
+        // Tick the fire first reaches each cell along the fuse.
+        let mut reached = [None; 45];
+        for tick in 0..400 {
+            engine.step();
+            for (_, x) in burning(&engine, "fuse") {
+                reached[x].get_or_insert(tick);
+            }
+        }
+        let (start, end) = (reached[5].unwrap(), reached[25].unwrap());
+        let expected = 20.0 / rate;
+        assert!(
+            ((end - start) as f32 - expected).abs() <= 2.0,
+            "20 cells took {} ticks, expected {}",
+            end - start,
+            expected
+        );
+    }
+
+    #[test]
+    fn lift_charge_clears_the_mortar() {
+        // A shell sitting on a lift charge at the bottom of a 60 cell tube.
+        let (width, height, tube) = (40, 200, 60);
+        let mut engine = SimulationEngine::with_seed(width, height, 3);
+        let mortar = Material::from_name("mortar").unwrap();
+        let lift = Material::from_name("lift_charge").unwrap();
+        let shell = Material::from_name("cardboard").unwrap();
+        let bottom = height - 1;
+        let mouth = bottom - tube;
+        let map = engine.map_mut();
+        for x in 10..20 {
+            map.add_material(bottom, x, mortar);
+        }
+        for y in mouth..bottom {
+            map.add_material(y, 10, mortar);
+            map.add_material(y, 19, mortar);
+        }
+        for x in 11..19 {
+            for y in bottom - 4..bottom {
+                map.add_material(y, x, lift);
+            }
+            for y in bottom - 10..bottom - 4 {
+                map.add_material(y, x, shell);
+            }
+        }
+        map.add_material(bottom - 1, 14, Material::from_name("fire").unwrap());
+
+        let cleared = (0..200).any(|_| {
+            engine.step();
+            let map = engine.map();
+            let lowest = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (y, x)))
+                .filter(|&(y, x)| map.contents_at_index(y, x).is_some_and(|c| c.mat == shell))
+                .map(|(y, _)| y)
+                .max();
+            lowest.is_some_and(|y| y < mouth)
+        });
+        assert!(cleared, "the shell never left the tube");
+    }
 }